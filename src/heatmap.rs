@@ -1,34 +1,72 @@
+pub use activity::ActivityType;
+use activity::{Mapping, TypeFilter};
 use chrono::{DateTime, Utc};
+use clean::Cleaning;
 use conv::prelude::*;
+use glob::Pattern;
 use image::{Rgb, RgbImage};
+use matching::Matcher;
+use metrics::{Limits, Metrics};
 use quick_xml::events::Event;
 use quick_xml::Reader;
+use regex::Regex;
+use region::Geofence;
+use schedule::Schedule;
 use simple_error::bail;
+use simplify::Simplify;
 use std::error::Error;
 use std::fmt;
 use std::fs;
 use std::path::PathBuf;
+use std::str::FromStr;
 
+pub mod activity;
+pub mod annotate;
+pub mod bins;
+pub mod clean;
+pub mod cluster;
+pub mod coverage;
+pub mod dedupe;
+pub mod diff;
+pub mod export;
 mod gpx;
+pub mod list;
+pub mod matching;
+pub mod metrics;
+pub mod osm;
+pub mod panels;
+pub mod period;
+pub mod privacy;
+pub mod ramp;
+pub mod region;
+pub mod schedule;
+pub mod simplify;
+pub mod stats;
 mod tcx;
+pub mod tiles;
 
 const R: f64 = 6371e3; // earth mean radius in meters
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
 pub struct Point {
     pub lat: f64,
     pub lng: f64,
 }
 
-enum XmlType {
+/// Format of a track file
+#[derive(Clone, Copy)]
+pub enum XmlType {
     Gpx,
     Tcx,
 }
 
-pub enum ActivityType {
-    Bike,
-    Run,
-    Walk,
+impl fmt::Display for XmlType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Gpx => write!(f, "GPX"),
+            Self::Tcx => write!(f, "TCX"),
+        }
+    }
 }
 
 impl fmt::Debug for Point {
@@ -37,6 +75,32 @@ impl fmt::Debug for Point {
     }
 }
 
+impl FromStr for Point {
+    type Err = String;
+
+    /// Parses a point in the form of lat,lng (ex: 40.7812,-73.9665)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let coords = s
+            .split(',')
+            .map(|c| c.trim().parse())
+            .collect::<Result<Vec<f64>, _>>()
+            .map_err(|_| {
+                format!("point must be in form of lat,lng (ex: 40.7812,-73.9665), got {s}")
+            })?;
+        match coords[..] {
+            [lat, lng] if (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lng) => {
+                Ok(Self { lat, lng })
+            }
+            [_, _] => Err(format!(
+                "latitude must be between -90 and 90 and longitude between -180 and 180, got {s}"
+            )),
+            _ => Err(format!(
+                "point must be in form of lat,lng (ex: 40.7812,-73.9665), got {s}"
+            )),
+        }
+    }
+}
+
 impl std::ops::Mul<f64> for Point {
     type Output = Self;
 
@@ -48,10 +112,30 @@ impl std::ops::Mul<f64> for Point {
     }
 }
 
-#[derive(PartialEq)]
+#[derive(Clone, PartialEq)]
 pub struct TrkPt {
     pub center: Point,
+    /// meters above sea level, if recorded
+    pub ele: Option<f64>,
+    pub time: Option<DateTime<Utc>>,
+}
+
+/// A track parsed from a file
+#[derive(Default)]
+pub struct Track {
+    pub pts: Vec<TrkPt>,
+    /// GPX `<type>` or TCX `Sport` of the track, if it has one
+    pub activity: Option<String>,
+    /// GPX `<name>` or TCX `<Notes>` of the track, if it has one
+    pub name: Option<String>,
+    /// GPX `<metadata>` time or TCX `StartTime` of the lap the points were read from, which `start` and `end` are checked against
     pub time: Option<DateTime<Utc>>,
+    /// whether the file has a time but it couldn't be parsed (noted in `warnings`), which leaves the track out when filtering by date
+    pub invalid_time: bool,
+    /// format of the file, if it was recognized
+    pub format: Option<XmlType>,
+    /// problems with parts of the file that were skipped (ex: points without coordinates)
+    pub warnings: Vec<String>,
 }
 
 impl fmt::Debug for TrkPt {
@@ -72,13 +156,140 @@ pub struct MapInfo {
     pub scale: Point,
 }
 
-/// Parses trkpt's from gpx or tcx file into vector
-pub fn get_pts(
+impl MapInfo {
+    #[must_use]
+    /// Linear transformation of `p` to (unrounded) x and y pixel coordinates on the map
+    /// Longitudes are wrapped to within 180 degrees of the center of the map, so maps crossing the antimeridian are continuous
+    pub fn pixel(&self, p: &Point) -> (f64, f64) {
+        (
+            (wrap_lng(p.lng - self.center.lng) + self.center.lng - self.min.lng) * self.scale.lng,
+            (p.lat - self.min.lat) * self.scale.lat,
+        )
+    }
+
+    #[must_use]
+    /// Inverse of `pixel`, transforming x and y pixel coordinates on the map to a point
+    pub fn point(&self, x: f64, y: f64) -> Point {
+        Point {
+            lat: y / self.scale.lat + self.min.lat,
+            lng: wrap_lng(x / self.scale.lng + self.min.lng),
+        }
+    }
+}
+
+/// Which tracks are loaded by `get_pts_from_files`
+#[derive(Clone, Copy, Default)]
+pub struct Filters<'a> {
+    /// only tracks of these types, or of any type if `None`
+    pub types: Option<&'a [ActivityType]>,
+    /// how activity types are read from files, or the built-in mapping if `None`
+    pub mapping: Option<&'a Mapping>,
+    /// no tracks of these types
+    pub exclude_types: &'a [ActivityType],
+    /// no tracks with a GPX `<name>` or TCX `<Notes>` matching this
+    pub exclude_name: Option<&'a Regex>,
+    /// no files or directories with paths matching these (when reading directories)
+    pub exclude_paths: &'a [Pattern],
+    /// GPS glitches removed from tracks, or none if `None`
+    pub cleaning: Option<&'a Cleaning>,
+    /// tracks split into separate tracks at gaps, or left whole if `None`
+    pub split_gaps: Option<GapRule>,
+    /// tracks snapped onto roads, or left as recorded if `None`
+    pub matcher: Option<&'a Matcher<'a>>,
+    /// tracks simplified and resampled, or left as recorded if `None`
+    pub simplify: Option<&'a Simplify>,
+    /// only tracks that started after this
+    pub start: Option<DateTime<Utc>>,
+    /// only tracks that started before this
+    pub end: Option<DateTime<Utc>>,
+    /// only tracks passing through this
+    pub geofence: Option<&'a Geofence>,
+    /// only tracks with distances, durations, and speeds within these
+    pub limits: Limits,
+    /// only tracks that started at these local times
+    pub schedule: Schedule,
+}
+
+impl Filters<'_> {
+    #[must_use]
+    /// Applies the filters that need a whole parsed track to `pts`, returning the tracks to load from it (if any)
+    pub fn select(&self, pts: Vec<TrkPt>) -> Vec<Vec<TrkPt>> {
+        if pts.is_empty()
+            || !self.schedule.allows(&pts)
+            || !(self.limits.is_empty() || self.limits.allows(&Metrics::new(&pts)))
+        {
+            return Vec::new();
+        }
+        let tracks = match self.split_gaps {
+            Some(gap) => gap.split(pts),
+            None => vec![pts],
+        };
+        match self.geofence {
+            Some(geofence) => tracks
+                .into_iter()
+                .flat_map(|pts| geofence.apply(pts))
+                .collect(),
+            None => tracks,
+        }
+    }
+
+    #[must_use]
+    /// Whether `track`, parsed without filters, matches the type, name, and date filters that `get_track` checks while parsing
+    pub fn allows(&self, track: &Track) -> bool {
+        let default_mapping = Mapping::default();
+        let type_filter = TypeFilter {
+            types: self.types,
+            exclude: self.exclude_types,
+            mapping: self.mapping.unwrap_or(&default_mapping),
+        };
+        track
+            .activity
+            .as_ref()
+            .is_none_or(|activity| type_filter.allows(activity))
+            && !track.name.as_ref().is_some_and(|name| {
+                self.exclude_name
+                    .is_some_and(|exclude_name| exclude_name.is_match(name))
+            })
+            && in_period(track.time, track.invalid_time, self.start, self.end)
+    }
+
+    #[must_use]
+    /// Cleans up, snaps to roads, and simplifies the points of `track`, like when loading it
+    pub fn process(&self, mut track: Track) -> Track {
+        let pts = std::mem::take(&mut track.pts);
+        let pts = match self.cleaning {
+            Some(cleaning) => {
+                let default_mapping = Mapping::default();
+                cleaning.apply(
+                    pts,
+                    track.activity.as_ref().and_then(|activity| {
+                        self.mapping.unwrap_or(&default_mapping).classify(activity)
+                    }),
+                )
+            }
+            None => pts,
+        };
+        let pts = match self.matcher {
+            Some(matcher) => matcher.apply(&pts),
+            None => pts,
+        };
+        track.pts = match self.simplify {
+            Some(simplify) => simplify.apply(pts),
+            None => pts,
+        };
+        track
+    }
+}
+
+/// Parses the track (points, activity type, and name) from gpx or tcx file
+/// Returns an empty track if it doesn't match `type_filter`, `exclude_name`, `start`, or `end`
+pub fn get_track(
     contents: &str,
-    type_filters: &Option<Vec<ActivityType>>,
-    start: &Option<DateTime<Utc>>,
-    end: &Option<DateTime<Utc>>,
-) -> Result<Vec<TrkPt>, Box<dyn Error>> {
+    type_filter: Option<&TypeFilter>,
+    exclude_name: Option<&Regex>,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+) -> Result<Track, Box<dyn Error>> {
     let mut reader = Reader::from_str(contents);
     reader.trim_text(true);
 
@@ -106,22 +317,58 @@ pub fn get_pts(
         _ => bail!("Expected <gpx> or <TrainingCenterDatabase>"),
     };
 
-    match file_type {
-        XmlType::Gpx => gpx::get_pts(reader, type_filters, start, end),
-        XmlType::Tcx => tcx::get_pts(reader, type_filters, start, end),
+    let mut track = match file_type {
+        XmlType::Gpx => gpx::get_pts(reader, type_filter, exclude_name, start, end)?,
+        XmlType::Tcx => tcx::get_pts(reader, type_filter, exclude_name, start, end)?,
+    };
+    track.format = Some(file_type);
+    Ok(track)
+}
+
+/// Whether a track with the file time `time` (see `Track::time`) is kept by the `start` and `end` (exclusive) filters
+/// Tracks without a time are kept, unless the time couldn't be parsed (`invalid`) and a date filter is set
+fn in_period(
+    time: Option<DateTime<Utc>>,
+    invalid: bool,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+) -> bool {
+    match time {
+        Some(time) => start.is_none_or(|start| time >= start) && end.is_none_or(|end| time < end),
+        None => !invalid || (start.is_none() && end.is_none()),
+    }
+}
+
+/// Reads the text (or CDATA) in the element `tag` until it ends
+pub(super) fn parse_text(
+    reader: &mut Reader<&[u8]>,
+    buf: &mut Vec<u8>,
+    tag: &[u8],
+) -> Result<String, Box<dyn Error>> {
+    let mut text = String::new();
+
+    loop {
+        buf.clear();
+
+        match reader.read_event(buf) {
+            Ok(Event::Text(e)) => text.push_str(&e.unescape_and_decode(reader)?),
+            Ok(Event::CData(e)) => text.push_str(std::str::from_utf8(&e)?),
+            Ok(Event::End(ref e)) if e.name() == tag => return Ok(text),
+            Ok(Event::Eof) => bail!("Hit EOF while reading text"),
+            Err(e) => bail!("Error at position {}: {:?}", reader.buffer_position(), e),
+            _ => (),
+        }
     }
 }
 
 #[must_use]
 /// Iterates over paths in `file_list` and tries to parse files or files in directories as gpx/tcx files
-/// Filters by `type_filter` (only returning tracks of the given type) and start/end dates (only returning tracks that start after `start` or before `end`)
-/// Returns a vector of vectors (one per processed file) of `TrkPts`
+/// Only returns tracks matching `filters`
+/// Returns a vector of vectors (one per processed file, or per part of a file clipped by a geofence) of `TrkPts`, each with the activity type of its file (if it's known)
 pub fn get_pts_from_files(
     file_list: &[PathBuf],
-    type_filters: &Option<Vec<ActivityType>>,
-    start: &Option<DateTime<Utc>>,
-    end: &Option<DateTime<Utc>>,
-) -> Vec<Vec<TrkPt>> {
+    filters: &Filters,
+) -> Vec<(Option<ActivityType>, Vec<TrkPt>)> {
     let mut trk_pts = Vec::new();
 
     for path in file_list {
@@ -129,22 +376,35 @@ pub fn get_pts_from_files(
             Ok(meta) => {
                 let f_type = meta.file_type();
                 if f_type.is_file() {
-                    match get_pts_file(path, type_filters, start, end) {
-                        Ok(pts) => {
-                            if !pts.is_empty() {
-                                trk_pts.push(pts);
+                    match get_pts_file(path, filters) {
+                        Ok(track) => {
+                            for warning in &track.warnings {
+                                eprintln!("Warning reading {}: {warning}", path.display());
                             }
+                            let default_mapping = Mapping::default();
+                            let activity = track.activity.as_deref().and_then(|activity| {
+                                filters
+                                    .mapping
+                                    .unwrap_or(&default_mapping)
+                                    .classify(activity)
+                            });
+                            trk_pts.extend(
+                                filters
+                                    .select(track.pts)
+                                    .into_iter()
+                                    .map(|pts| (activity, pts)),
+                            );
                         }
-                        Err(e) => eprintln!("Error reading {path:?}: {e}"),
+                        Err(e) => eprintln!("Error reading {}: {e}", path.display()),
                     }
                 } else if f_type.is_dir() {
-                    let mut dir_pts = get_pts_dir(path, type_filters, start, end);
+                    let mut dir_pts = get_pts_dir(path, filters);
                     trk_pts.append(&mut dir_pts);
                 } else {
-                    eprintln!("Unable to read {path:?}");
+                    eprintln!("Unable to read {}", path.display());
                 }
             }
-            Err(e) => eprintln!("Error stating {path:?}: {e}"),
+            Err(e) => eprintln!("Error stating {}: {e}", path.display()),
         }
     }
 
@@ -152,44 +412,63 @@ pub fn get_pts_from_files(
 }
 
 /// Attempts to parse `file` as gpx or tcx file and read it into `TrkPt`s
-/// Filters by `type_filter` (only returning tracks of the given type) and start/end dates (only returning tracks that start after `start` or before `end`)
-/// Returns a vector of `TrkPts` of the waypoints in the file
-pub fn get_pts_file(
-    file: &PathBuf,
-    type_filters: &Option<Vec<ActivityType>>,
-    start: &Option<DateTime<Utc>>,
-    end: &Option<DateTime<Utc>>,
-) -> Result<Vec<TrkPt>, Box<dyn Error>> {
+/// Filters by activity type, name, and start/end dates from `filters`, removes GPS glitches, snaps to roads, and simplifies (other filters are applied to the returned points by `Filters::select`)
+/// Returns the track in the file, with its points cleaned up
+pub fn get_pts_file(file: &PathBuf, filters: &Filters) -> Result<Track, Box<dyn Error>> {
     let contents = fs::read_to_string(file)?;
-    get_pts(&contents, type_filters, start, end)
+    let default_mapping = Mapping::default();
+    let mapping = filters.mapping.unwrap_or(&default_mapping);
+    let type_filter =
+        (filters.types.is_some() || !filters.exclude_types.is_empty()).then_some(TypeFilter {
+            types: filters.types,
+            exclude: filters.exclude_types,
+            mapping,
+        });
+    let track = get_track(
+        &contents,
+        type_filter.as_ref(),
+        filters.exclude_name,
+        filters.start,
+        filters.end,
+    )?;
+    Ok(filters.process(track))
 }
 
 #[must_use]
 /// Iterates over entires in directory and tries to parse them as gpx or tcx files if they're files.
-/// Filters by `type_filter` (only returning tracks of the given type) and start/end dates (only returning tracks that start after `start` or before `end`)
-/// Returns a vector of vectors (one per processed file) of `TrkPts` from the directory contents
+/// Skips entries with paths matching `filters.exclude_paths`
+/// Only returns tracks matching `filters`
+/// Returns a vector of vectors (one per processed file, or per part of a file clipped by a geofence) of `TrkPts` from the directory contents, each with the activity type of its file (if it's known)
 pub fn get_pts_dir(
     directory: &PathBuf,
-    type_filters: &Option<Vec<ActivityType>>,
-    start: &Option<DateTime<Utc>>,
-    end: &Option<DateTime<Utc>>,
-) -> Vec<Vec<TrkPt>> {
+    filters: &Filters,
+) -> Vec<(Option<ActivityType>, Vec<TrkPt>)> {
     let mut file_list = Vec::new();
 
     for entry in fs::read_dir(directory).expect("Error reading directory") {
         match entry {
-            Ok(file) => file_list.push(file.path()),
+            Ok(file) => {
+                let path = file.path();
+                if !filters
+                    .exclude_paths
+                    .iter()
+                    .any(|pattern| pattern.matches_path(&path))
+                {
+                    file_list.push(path);
+                }
+            }
             Err(e) => eprintln!("Error reading directory entry: {e}"),
         }
     }
 
-    get_pts_from_files(&file_list, type_filters, start, end)
+    get_pts_from_files(&file_list, filters)
 }
 
 #[must_use]
 /// Returns two points that are comprised of the lowest latitude and lowest longitude and highest latitude and highest longitude within `pts`.
 /// Note that these values are all considered independently and not as a point, so an input of [[35, 77], [33, 78]] would return ([33, 77], [35, 78]), meaning the output points may not exist in the input.
-pub fn min_max(pts: &[Vec<TrkPt>]) -> (Point, Point) {
+/// Longitudes span the shortest range containing every point, so for points on both sides of the antimeridian the highest longitude will be above 180.
+pub fn min_max<'a>(pts: impl IntoIterator<Item = &'a Vec<TrkPt>>) -> (Point, Point) {
     let mut min = Point {
         lat: 90.0,
         lng: 180.0,
@@ -198,18 +477,112 @@ pub fn min_max(pts: &[Vec<TrkPt>]) -> (Point, Point) {
         lat: -90.0,
         lng: -180.0,
     };
+    let mut lngs = Vec::new();
     for v in pts {
         for pt in v {
             max.lat = max.lat.max(pt.center.lat);
             min.lat = min.lat.min(pt.center.lat);
-            max.lng = max.lng.max(pt.center.lng);
-            min.lng = min.lng.min(pt.center.lng);
+            lngs.push(pt.center.lng);
         }
     }
+    lngs.sort_by(f64::total_cmp);
+    unwrap_lngs(&mut lngs);
+    if let (Some(&first), Some(&last)) = (lngs.first(), lngs.last()) {
+        min.lng = first;
+        max.lng = last;
+    }
 
     (min, max)
 }
 
+#[must_use]
+/// Like `min_max`, but ignores outliers by only covering the central `percentile` (0 to 100) of latitudes and of longitudes in `pts`
+/// e.g. 98 leaves out the lowest and highest 1% of each, so a few stray points (like a GPS glitch to 0, 0) don't stretch the map
+pub fn percentile_bounds<'a>(
+    pts: impl IntoIterator<Item = &'a Vec<TrkPt>>,
+    percentile: f64,
+) -> (Point, Point) {
+    let mut lats = Vec::new();
+    let mut lngs = Vec::new();
+    for v in pts {
+        for pt in v {
+            lats.push(pt.center.lat);
+            lngs.push(pt.center.lng);
+        }
+    }
+    if lats.is_empty() {
+        return min_max(&[]);
+    }
+    lats.sort_by(f64::total_cmp);
+    lngs.sort_by(f64::total_cmp);
+    unwrap_lngs(&mut lngs);
+
+    // fraction of values left out at each end
+    let trim = (100.0 - percentile).clamp(0.0, 100.0) / 200.0;
+    let (min_lat, max_lat) = trimmed_range(&lats, trim);
+    let (min_lng, max_lng) = trimmed_range(&lngs, trim);
+    (
+        Point {
+            lat: min_lat,
+            lng: min_lng,
+        },
+        Point {
+            lat: max_lat,
+            lng: max_lng,
+        },
+    )
+}
+
+/// Adds 360 to the longitudes in `sorted` west of the widest gap between consecutive longitudes, if that gap is wider than the gap across the antimeridian
+/// This keeps `sorted` sorted while making the range from its first to last longitude as short as possible
+fn unwrap_lngs(sorted: &mut [f64]) {
+    let (Some(&first), Some(&last)) = (sorted.first(), sorted.last()) else {
+        return;
+    };
+    let mut widest = first + 360.0 - last;
+    let mut split = 0;
+    for (i, pair) in sorted.windows(2).enumerate() {
+        if pair[1] - pair[0] > widest {
+            widest = pair[1] - pair[0];
+            split = i + 1;
+        }
+    }
+
+    sorted.rotate_left(split);
+    let len = sorted.len();
+    for lng in &mut sorted[len - split..] {
+        *lng += 360.0;
+    }
+}
+
+/// Lowest and highest values of non-empty `sorted` after leaving out the fraction `trim` of values at each end
+fn trimmed_range(sorted: &[f64], trim: f64) -> (f64, f64) {
+    let last = sorted.len() - 1;
+    #[allow(clippy::cast_precision_loss)]
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_sign_loss)]
+    let skip = ((sorted.len() as f64 * trim).floor() as usize).min(last / 2);
+    (sorted[skip], sorted[last - skip])
+}
+
+#[must_use]
+/// Returns the earliest and latest timestamps of all points in `pts`, or `None` if no points have a timestamp
+pub fn time_range(pts: &[Vec<TrkPt>]) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    pts.iter()
+        .flatten()
+        .filter_map(|pt| pt.time)
+        .fold(None, |range, time| match range {
+            Some((first, last)) => Some((time.min(first), time.max(last))),
+            None => Some((time, time)),
+        })
+}
+
+#[must_use]
+/// Wraps `lng` into the range of -180 (inclusive) to 180 (exclusive) degrees
+pub fn wrap_lng(lng: f64) -> f64 {
+    (lng + 180.0).rem_euclid(360.0) - 180.0
+}
+
 #[must_use]
 /// Computes great-circle distance between p1 and p2
 pub fn haversine(p1: &Point, p2: &Point) -> f64 {
@@ -230,6 +603,31 @@ pub fn haversine(p1: &Point, p2: &Point) -> f64 {
     R * c
 }
 
+#[must_use]
+/// East and north offsets in meters of `p` from `origin`, treating the earth as flat around `origin`
+pub fn offset_meters(origin: &Point, p: &Point) -> (f64, f64) {
+    let meters_per_degree = R.to_radians();
+    (
+        wrap_lng(p.lng - origin.lng) * origin.lat.to_radians().cos() * meters_per_degree,
+        (p.lat - origin.lat) * meters_per_degree,
+    )
+}
+
+#[must_use]
+/// Approximate distance in meters from `p` to the segment from `a` to `b`, treating the earth as flat around `p`
+pub fn segment_distance(p: &Point, a: &Point, b: &Point) -> f64 {
+    let ((ax, ay), (bx, by)) = (offset_meters(p, a), offset_meters(p, b));
+    let (dx, dy) = (bx - ax, by - ay);
+    let length = dx.mul_add(dx, dy * dy);
+    // fraction of the way along the segment of the closest point to p (at the origin)
+    let t = if length > 0.0 {
+        (-(ax.mul_add(dx, ay * dy)) / length).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    t.mul_add(dx, ax).hypot(t.mul_add(dy, ay))
+}
+
 #[must_use]
 /// Finds destination point along great-circle path (in meters) from start point p towards bearing
 pub fn destination(p: &Point, bearing: f64, distance: f64) -> Point {
@@ -254,8 +652,6 @@ pub fn destination(p: &Point, bearing: f64, distance: f64) -> Point {
 #[allow(clippy::doc_markdown)]
 /// Based on image size and lat/lng ranges, calculates the center and MapBox zoom level of a map, and the new minimum lat/lng and scale for linear transformation from lat/lng to pixel
 pub fn calculate_map(pixels: u32, min: &Point, max: &Point, scale_multiplier: f64) -> MapInfo {
-    let pixels = f64::from(pixels);
-
     // simple centers
     let lat = min.lat + (max.lat - min.lat) / 2.0;
     let lng = min.lng + (max.lng - min.lng) / 2.0;
@@ -266,14 +662,51 @@ pub fn calculate_map(pixels: u32, min: &Point, max: &Point, scale_multiplier: f6
     // take the great of the two and use it to calculate zoom level
     let map_meters = map_height_meters.max(map_width_meters);
 
-    let meters_per_pixel = (map_meters / pixels) * 1.1; //add padding so min/max aren't right against edge of map
+    let meters_per_pixel = (map_meters / f64::from(pixels)) * 1.1; //add padding so min/max aren't right against edge of map
 
     // calculate MapBox zoom level at center latitude (this will also be inaccuate for larger maps)
     let zoom = ((10_018_755.0 * lat.to_radians().cos()) / meters_per_pixel).ln()
         / std::f64::consts::LN_2
         - 7.0;
 
-    let center = Point { lat, lng };
+    frame_map(
+        pixels,
+        &Point { lat, lng },
+        zoom,
+        meters_per_pixel,
+        scale_multiplier,
+    )
+}
+
+#[must_use]
+#[allow(clippy::doc_markdown)]
+/// Calculates a map of `pixels` x `pixels` centered on `center` at MapBox zoom level `zoom`, with the minimum lat/lng and scale for linear transformation from lat/lng to pixel
+pub fn calculate_map_at_zoom(
+    pixels: u32,
+    center: &Point,
+    zoom: f64,
+    scale_multiplier: f64,
+) -> MapInfo {
+    // inverse of the zoom level calculation in calculate_map
+    let meters_per_pixel = 10_018_755.0 * center.lat.to_radians().cos() / (zoom + 7.0).exp2();
+
+    frame_map(pixels, center, zoom, meters_per_pixel, scale_multiplier)
+}
+
+/// Finds the minimum lat/lng and scale of a map of `pixels` x `pixels` around `center` with `meters_per_pixel`
+fn frame_map(
+    pixels: u32,
+    center: &Point,
+    zoom: f64,
+    meters_per_pixel: f64,
+    scale_multiplier: f64,
+) -> MapInfo {
+    let pixels = f64::from(pixels);
+    // the center may be past the antimeridian when the map crosses it
+    let center = Point {
+        lat: center.lat,
+        lng: wrap_lng(center.lng),
+    };
 
     // calculate new min/max points on map by finding destination point from center to corners
     let dist_to_edge = meters_per_pixel * pixels / 2.0;
@@ -295,23 +728,21 @@ pub fn calculate_map(pixels: u32, min: &Point, max: &Point, scale_multiplier: f6
 }
 
 #[must_use]
-/// Overlays dots with color `track_color` from `trk_pts` on `map_image` using scaling information in `map_info`
-/// `factor` is the multiplier of a mapped pixels opacity (the pixel opacity of the track layer is `factor` / 75th percentile of number of tracks greater than 1 on all pixels)
-pub fn overlay_image(
-    mut map_image: RgbImage,
+/// Counts how many times each pixel of a `width` x `height` map is part of a track from `trk_pts`, using scaling information in `map_info`
+/// Consecutive points are joined by lines unless `gap` separates them, and the returned counts are indexed by x and then y
+pub fn density(
     map_info: &MapInfo,
+    width: u32,
+    height: u32,
     trk_pts: &[Vec<TrkPt>],
-    track_color: Rgb<u8>,
-    factor: f64,
-    min_alpha: f64,
-) -> RgbImage {
-    let trks = trk_pts.len();
-    let width = i32::value_from(map_image.width()).expect("image width must fit in i32");
-    let height = i32::value_from(map_image.height()).expect("image height must fit in i32");
+    gap: &GapRule,
+) -> Vec<Vec<u32>> {
+    let width = i32::value_from(width).expect("image width must fit in i32");
+    let height = i32::value_from(height).expect("image height must fit in i32");
 
     // count of how many times a pixel is part of a track, will be multiplied by single step and capped to 2 during compositing
     #[allow(clippy::cast_sign_loss)]
-    let mut factors = vec![vec![0; width as usize]; height as usize];
+    let mut factors = vec![vec![0; height as usize]; width as usize];
 
     // used to clamp dots (and neighbors) from going beyond image bounds
     let max_x = width - 2;
@@ -321,11 +752,10 @@ pub fn overlay_image(
     for v in trk_pts {
         let mut prev_x: Option<i32> = None; //the x of the last pixel, for line drawing
         let mut prev_y: Option<i32> = None; //the y of the last pixel
-        let mut prev_time: Option<DateTime<Utc>> = None; //the timestamp of the TrkPt used to draw the last pixel
+        let mut prev: Option<&TrkPt> = None; //the TrkPt used to draw the last pixel
         for pt in v {
-            // linear transformations
-            let x = ((pt.center.lng - map_info.min.lng) * map_info.scale.lng).round() as i32;
-            let y = ((pt.center.lat - map_info.min.lat) * map_info.scale.lat).round() as i32;
+            let (x, y) = map_info.pixel(&pt.center);
+            let (x, y) = (x.round() as i32, y.round() as i32);
             if x < 1 || x > max_x || y < 1 || y > max_y {
                 continue;
             }
@@ -335,15 +765,11 @@ pub fn overlay_image(
                 let prev_y = prev_y.unwrap();
                 if prev_x == x && prev_y == y {
                     // dont redraw on same pixel repeatedly (to try and prevent overly shading "slow" sections)
-                    prev_time = pt.time;
+                    prev = Some(pt);
                     continue;
                 }
 
-                // dont draw a line between points that are more than 5 seconds apart
-                if pt.time.is_none()
-                    || prev_time.is_none()
-                    || (pt.time.unwrap() - prev_time.unwrap()).num_seconds().abs() <= 5
-                {
+                if prev.is_some_and(|prev| gap.connects(prev, pt)) {
                     let (x1, y1, x2, y2) = if prev_x >= x {
                         (x, y, prev_x, prev_y)
                     } else {
@@ -379,51 +805,56 @@ pub fn overlay_image(
 
             prev_x = Some(x);
             prev_y = Some(y);
-            prev_time = pt.time;
+            prev = Some(pt);
         }
     }
 
-    #[allow(clippy::cast_sign_loss)]
-    let mut sorted = Vec::with_capacity(width as usize * height as usize);
+    factors
+}
 
-    for row in &factors {
-        for &factor in row {
-            if factor > 1 {
-                sorted.push(factor);
+#[must_use]
+/// Opacity added by each track on a pixel so that pixels in the 75th percentile of pixel track counts greater than 1 across `grids` (as returned by `density`) are fully opaque
+/// `factor` is the multiplier of a mapped pixels opacity (the returned step is `factor` / that 75th percentile)
+pub fn single_step(grids: &[&[Vec<u32>]], factor: f64) -> f64 {
+    let mut sorted = Vec::new();
+
+    for factors in grids {
+        for row in *factors {
+            for &factor in row {
+                if factor > 1 {
+                    sorted.push(factor);
+                }
             }
         }
     }
 
     sorted.sort_unstable();
 
-    // Take 75th percentile data point so that the top 25% "densest" pixels are all max alpha
-    let single_step = factor / f64::from(sorted[sorted.len() / 4 * 3]);
-    println!("Tracks: {trks} -- Step: {single_step:.2}");
+    // Take 75th percentile data point so that the top 25% "densest" pixels are all max alpha (or a single track if no pixels have more than one)
+    factor / f64::from(sorted.get(sorted.len() / 4 * 3).copied().unwrap_or(1))
+}
 
+#[must_use]
+/// Overlays pixel track counts `factors` (as returned by `density`) on `map_image` with color `track_color`
+/// Each track on a pixel adds `single_step` opacity, and pixels with any tracks are at least `min_alpha` opaque
+pub fn overlay_image(
+    mut map_image: RgbImage,
+    factors: &[Vec<u32>],
+    track_color: Rgb<u8>,
+    single_step: f64,
+    min_alpha: f64,
+) -> RgbImage {
     // composit path_image onto map_image
     #[allow(clippy::cast_possible_truncation)]
-    #[allow(clippy::cast_sign_loss)]
     for (x, row) in factors.iter().enumerate() {
         for (y, &factor) in row.iter().enumerate() {
             let intensity = f64::from(factor) * single_step;
             if intensity > 0.0 {
                 let alpha = intensity.clamp(min_alpha, 1.0);
 
-                let map_pixel = map_image.get_pixel_mut(x as u32, y as u32);
-                let Rgb(map_data) = *map_pixel;
-
-                let mut new_pixel = [0; 3];
-                // composit each color channel
-                for i in 0..3 {
-                    let color_a = f64::from(track_color[i]);
-                    let color_b = f64::from(map_data[i]);
-                    new_pixel[i] = (color_a.mul_add(alpha, color_b * (1.0 - alpha)))
-                        .clamp(0.0, 255.0)
-                        .round() as u8;
-                }
-
                 // save new composited pixel to map_image
-                *map_pixel = Rgb(new_pixel);
+                let map_pixel = map_image.get_pixel_mut(x as u32, y as u32);
+                *map_pixel = blend(track_color, *map_pixel, alpha);
             }
         }
     }
@@ -431,6 +862,74 @@ pub fn overlay_image(
     map_image
 }
 
+/// When consecutive track points are too far apart to be joined by a line
+#[derive(Clone, Copy)]
+pub struct GapRule {
+    /// most whole seconds between points with timestamps, ignoring fractions of a second (infinite to never break by time)
+    pub seconds: f64,
+    /// most meters between points (infinite to never break by distance)
+    pub meters: f64,
+}
+
+impl Default for GapRule {
+    fn default() -> Self {
+        Self {
+            seconds: 5.0,
+            meters: f64::INFINITY,
+        }
+    }
+}
+
+impl GapRule {
+    #[must_use]
+    /// Whether consecutive track points `prev` and `pt` should be joined by a line
+    pub fn connects(&self, prev: &TrkPt, pt: &TrkPt) -> bool {
+        // whole seconds, so gaps up to 5.999 seconds are joined by the default rule as they always have been
+        #[allow(clippy::cast_precision_loss)]
+        let within_time = match (prev.time, pt.time) {
+            (Some(prev_time), Some(time)) => {
+                (time - prev_time).num_seconds().abs() as f64 <= self.seconds
+            }
+            _ => true,
+        };
+        within_time
+            && (self.meters.is_infinite() || haversine(&prev.center, &pt.center) <= self.meters)
+    }
+
+    #[must_use]
+    /// Splits `pts` into separate tracks wherever consecutive points aren't connected
+    pub fn split(&self, pts: Vec<TrkPt>) -> Vec<Vec<TrkPt>> {
+        let mut tracks: Vec<Vec<TrkPt>> = Vec::new();
+        for pt in pts {
+            match tracks.last_mut() {
+                Some(track) if track.last().is_some_and(|prev| self.connects(prev, &pt)) => {
+                    track.push(pt);
+                }
+                _ => tracks.push(vec![pt]),
+            }
+        }
+        tracks
+    }
+}
+
+#[must_use]
+/// Composits `top` over `bottom`, with `alpha` being the opacity of `top`
+pub fn blend(top: Rgb<u8>, bottom: Rgb<u8>, alpha: f64) -> Rgb<u8> {
+    let mut new_pixel = [0; 3];
+    // composit each color channel
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_sign_loss)]
+    for i in 0..3 {
+        let color_a = f64::from(top[i]);
+        let color_b = f64::from(bottom[i]);
+        new_pixel[i] = (color_a.mul_add(alpha, color_b * (1.0 - alpha)))
+            .clamp(0.0, 255.0)
+            .round() as u8;
+    }
+
+    Rgb(new_pixel)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -475,6 +974,7 @@ mod tests {
                     lat: 30.2430140,
                     lng: -97.8100270,
                 },
+                ele: None,
                 time: None,
             },
             TrkPt {
@@ -482,6 +982,7 @@ mod tests {
                     lat: 30.2429950,
                     lng: -97.8100160,
                 },
+                ele: None,
                 time: None,
             },
             TrkPt {
@@ -489,6 +990,7 @@ mod tests {
                     lat: 30.2428630,
                     lng: -97.8101550,
                 },
+                ele: None,
                 time: None,
             },
             TrkPt {
@@ -496,6 +998,7 @@ mod tests {
                     lat: 30.2428470,
                     lng: -97.8102190,
                 },
+                ele: None,
                 time: None,
             },
             TrkPt {
@@ -503,6 +1006,7 @@ mod tests {
                     lat: 30.2428310,
                     lng: -97.8102830,
                 },
+                ele: None,
                 time: None,
             },
             TrkPt {
@@ -510,6 +1014,7 @@ mod tests {
                     lat: 30.2427670,
                     lng: -97.8105240,
                 },
+                ele: None,
                 time: None,
             },
             TrkPt {
@@ -517,6 +1022,7 @@ mod tests {
                     lat: 30.2427500,
                     lng: -97.8105730,
                 },
+                ele: None,
                 time: None,
             },
             TrkPt {
@@ -524,6 +1030,7 @@ mod tests {
                     lat: 30.2427330,
                     lng: -97.8106130,
                 },
+                ele: None,
                 time: None,
             },
         ]]);
@@ -533,6 +1040,165 @@ mod tests {
         assert!((max.lng + 97.8100160).abs() < f64::EPSILON);
     }
 
+    #[test]
+    fn calculate_map_at_zoom_test() {
+        let map_info = calculate_map(
+            1280,
+            &Point {
+                lat: 40.76,
+                lng: -73.99,
+            },
+            &Point {
+                lat: 40.80,
+                lng: -73.94,
+            },
+            2.0,
+        );
+        let at_zoom = calculate_map_at_zoom(1280, &map_info.center, map_info.zoom, 2.0);
+        assert!((at_zoom.min.lat - map_info.min.lat).abs() < 1e-9);
+        assert!((at_zoom.min.lng - map_info.min.lng).abs() < 1e-9);
+        assert!((at_zoom.scale.lng - map_info.scale.lng).abs() < 1e-6);
+    }
+
+    #[test]
+    fn antimeridian_test() {
+        // a track from Fiji east across the antimeridian
+        let pts = vec![
+            TrkPt {
+                center: Point {
+                    lat: -17.0,
+                    lng: 178.0,
+                },
+                ele: None,
+                time: None,
+            },
+            TrkPt {
+                center: Point {
+                    lat: -16.0,
+                    lng: -179.0,
+                },
+                ele: None,
+                time: None,
+            },
+        ];
+        let (min, max) = min_max(&[pts]);
+        assert!((min.lng - 178.0).abs() < f64::EPSILON);
+        assert!((max.lng - 181.0).abs() < f64::EPSILON);
+
+        let map_info = calculate_map(1280, &min, &max, 2.0);
+        assert!((map_info.center.lng - 179.5).abs() < 1e-9);
+        assert!(map_info.zoom > 6.0);
+        // both sides of the antimeridian are on the map, in order
+        let (west, _) = map_info.pixel(&Point {
+            lat: -17.0,
+            lng: 178.0,
+        });
+        let (east, _) = map_info.pixel(&Point {
+            lat: -16.0,
+            lng: -179.0,
+        });
+        assert!(0.0 < west && west < east && east < 2560.0);
+        let p = map_info.point(east, 0.0);
+        assert!((p.lng + 179.0).abs() < 1e-9);
+
+        // past the antimeridian, the center wraps to the western hemisphere
+        let map_info = calculate_map(
+            1280,
+            &Point {
+                lat: -17.0,
+                lng: 179.0,
+            },
+            &Point {
+                lat: -16.0,
+                lng: 183.0,
+            },
+            2.0,
+        );
+        assert!((map_info.center.lng + 179.0).abs() < 1e-9);
+        let (x, _) = map_info.pixel(&Point {
+            lat: -16.0,
+            lng: 179.5,
+        });
+        assert!(0.0 < x && x < 1280.0);
+    }
+
+    #[test]
+    fn percentile_bounds_test() {
+        // 99 points along a line and a single glitch at 0, 0
+        let mut pts: Vec<TrkPt> = (0..99)
+            .map(|i| TrkPt {
+                center: Point {
+                    lat: 30.0 + f64::from(i) * 0.001,
+                    lng: -97.0,
+                },
+                ele: None,
+                time: None,
+            })
+            .collect();
+        pts.push(TrkPt {
+            center: Point { lat: 0.0, lng: 0.0 },
+            ele: None,
+            time: None,
+        });
+        let (min, max) = percentile_bounds(&[pts], 98.0);
+        assert!((min.lat - 30.0).abs() < f64::EPSILON);
+        assert!((max.lat - 30.097).abs() < 1e-9);
+        assert!((max.lng + 97.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn gap_rule_test() {
+        // ~111m apart every 10 seconds, then a 1km jump and a 30 second pause
+        let start: DateTime<Utc> = "2024-01-01T00:00:00Z".parse().unwrap();
+        let pts = |lats: &[f64], seconds: &[i64]| -> Vec<TrkPt> {
+            lats.iter()
+                .zip(seconds)
+                .map(|(&lat, &s)| TrkPt {
+                    center: Point { lat, lng: -97.0 },
+                    ele: None,
+                    time: Some(start + chrono::Duration::seconds(s)),
+                })
+                .collect()
+        };
+        let track = || {
+            pts(
+                &[30.0, 30.001, 30.002, 30.011, 30.012],
+                &[0, 10, 20, 30, 60],
+            )
+        };
+        let lens =
+            |gap: GapRule| -> Vec<usize> { gap.split(track()).iter().map(Vec::len).collect() };
+
+        assert_eq!(lens(GapRule::default()), vec![1, 1, 1, 1, 1]);
+        let by_time = GapRule {
+            seconds: 15.0,
+            meters: f64::INFINITY,
+        };
+        assert_eq!(lens(by_time), vec![4, 1]);
+        let by_distance = GapRule {
+            seconds: f64::INFINITY,
+            meters: 500.0,
+        };
+        assert_eq!(lens(by_distance), vec![3, 2]);
+        let both = GapRule {
+            seconds: 15.0,
+            meters: 500.0,
+        };
+        assert_eq!(lens(both), vec![3, 1, 1]);
+
+        // like before gaps were configurable, the default only counts whole seconds
+        let pause = |millis| TrkPt {
+            center: Point {
+                lat: 30.0,
+                lng: -97.0,
+            },
+            ele: None,
+            time: Some(start + chrono::Duration::milliseconds(millis)),
+        };
+        assert!(GapRule::default().connects(&pause(0), &pause(5999)));
+        assert!(!GapRule::default().connects(&pause(0), &pause(6000)));
+    }
+
     #[test]
     #[allow(clippy::too_many_lines)]
     #[allow(clippy::unreadable_literal)]
@@ -583,13 +1249,14 @@ mod tests {
 </gpx>
 "#;
         assert_eq!(
-            get_pts(gpx, &None, &None, &None).unwrap(),
+            get_track(gpx, None, None, None, None).unwrap().pts,
             vec![
                 TrkPt {
                     center: Point {
                         lat: 30.2430140,
                         lng: -97.8100160
                     },
+                    ele: Some(177.8),
                     time: Some("2019-11-10T20:49:52Z".parse::<DateTime<Utc>>().unwrap())
                 },
                 TrkPt {
@@ -597,6 +1264,7 @@ mod tests {
                         lat: 30.2429950,
                         lng: -97.8100270
                     },
+                    ele: Some(177.6),
                     time: Some("2019-11-10T20:49:53Z".parse::<DateTime<Utc>>().unwrap())
                 },
                 TrkPt {
@@ -604,6 +1272,7 @@ mod tests {
                         lat: 30.2428630,
                         lng: -97.8101550
                     },
+                    ele: Some(177.9),
                     time: Some("2019-11-10T20:49:54Z".parse::<DateTime<Utc>>().unwrap())
                 },
                 TrkPt {
@@ -611,6 +1280,7 @@ mod tests {
                         lat: 30.2428470,
                         lng: -97.8102190
                     },
+                    ele: Some(178.0),
                     time: Some("2019-11-10T20:49:55Z".parse::<DateTime<Utc>>().unwrap())
                 },
                 TrkPt {
@@ -618,6 +1288,7 @@ mod tests {
                         lat: 30.2428310,
                         lng: -97.8102830
                     },
+                    ele: Some(178.2),
                     time: Some("2019-11-10T20:49:56Z".parse::<DateTime<Utc>>().unwrap())
                 },
                 TrkPt {
@@ -625,6 +1296,7 @@ mod tests {
                         lat: 30.2427670,
                         lng: -97.8105240
                     },
+                    ele: Some(179.0),
                     time: Some("2019-11-10T20:49:57Z".parse::<DateTime<Utc>>().unwrap())
                 },
                 TrkPt {
@@ -632,6 +1304,7 @@ mod tests {
                         lat: 30.2427500,
                         lng: -97.8105730
                     },
+                    ele: Some(179.1),
                     time: Some("2019-11-10T20:49:58Z".parse::<DateTime<Utc>>().unwrap())
                 },
                 TrkPt {
@@ -639,10 +1312,67 @@ mod tests {
                         lat: 30.2427330,
                         lng: -97.8106130
                     },
+                    ele: Some(179.3),
                     time: Some("2019-11-10T20:49:59Z".parse::<DateTime<Utc>>().unwrap())
                 }
             ]
         );
+        assert!(
+            get_track(gpx, None, Some(&Regex::new("^Ride$").unwrap()), None, None)
+                .unwrap()
+                .pts
+                .is_empty()
+        );
+        let track = get_track(gpx, None, None, None, None).unwrap();
+        assert_eq!(track.activity.as_deref(), Some("1"));
+        assert_eq!(track.name.as_deref(), Some("Ride"));
+        let no_virtual = activity::TypeFilter {
+            types: None,
+            exclude: &[ActivityType::VirtualRide],
+            mapping: &Mapping::default(),
+        };
+        assert_eq!(
+            get_track(gpx, Some(&no_virtual), None, None, None)
+                .unwrap()
+                .pts
+                .len(),
+            8
+        );
+        // end is exclusive, so a track starting exactly at it is left out
+        let started: DateTime<Utc> = "2019-05-09T02:39:00Z".parse().unwrap();
+        assert!(get_track(gpx, None, None, None, Some(started))
+            .unwrap()
+            .pts
+            .is_empty());
+        // the same filters applied to a track parsed without them
+        assert_eq!(track.time, Some(started));
+        assert!(Filters::default().allows(&track));
+        assert!(!Filters {
+            end: Some(started),
+            ..Filters::default()
+        }
+        .allows(&track));
+        assert!(!Filters {
+            exclude_name: Some(&Regex::new("^Ride$").unwrap()),
+            ..Filters::default()
+        }
+        .allows(&track));
+
+        // a malformed metadata time is only a problem when filtering by date
+        let gpx = gpx.replace("2019-05-09T02:39:00Z", "yesterday");
+        let track = get_track(&gpx, None, None, None, None).unwrap();
+        assert_eq!(track.pts.len(), 8);
+        assert!(track.time.is_none());
+        assert_eq!(track.warnings.len(), 1);
+        assert!(!Filters {
+            end: Some(started),
+            ..Filters::default()
+        }
+        .allows(&track));
+        assert!(get_track(&gpx, None, None, None, Some(started))
+            .unwrap()
+            .pts
+            .is_empty());
     }
 
     #[test]
@@ -725,13 +1455,14 @@ mod tests {
  </Activities>
 </TrainingCenterDatabase>"#;
         assert_eq!(
-            get_pts(tcx, &None, &None, &None).unwrap(),
+            get_track(tcx, None, None, None, None).unwrap().pts,
             vec![
                 TrkPt {
                     center: Point {
                         lat: 30.2431060,
                         lng: -97.8099600
                     },
+                    ele: Some(178.3),
                     time: Some("2019-11-15T22:25:38Z".parse::<DateTime<Utc>>().unwrap())
                 },
                 TrkPt {
@@ -739,6 +1470,7 @@ mod tests {
                         lat: 30.2430710,
                         lng: -97.8099760
                     },
+                    ele: Some(178.1),
                     time: Some("2019-11-15T22:25:39Z".parse::<DateTime<Utc>>().unwrap())
                 },
                 TrkPt {
@@ -746,9 +1478,25 @@ mod tests {
                         lat: 30.2430000,
                         lng: -97.8100070
                     },
+                    ele: Some(177.7),
                     time: Some("2019-11-15T22:25:40Z".parse::<DateTime<Utc>>().unwrap())
                 }
             ]
         );
+
+        // a malformed lap time is only a problem when filtering by date
+        let tcx = tcx.replace(
+            r#"StartTime="2019-11-15T21:54:00Z""#,
+            r#"StartTime="last Friday""#,
+        );
+        let track = get_track(&tcx, None, None, None, None).unwrap();
+        assert_eq!(track.pts.len(), 3);
+        assert!(track.time.is_none());
+        assert_eq!(track.warnings.len(), 1);
+        let start: DateTime<Utc> = "2019-01-01T00:00:00Z".parse().unwrap();
+        assert!(get_track(&tcx, None, None, Some(start), None)
+            .unwrap()
+            .pts
+            .is_empty());
     }
 }
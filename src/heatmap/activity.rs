@@ -0,0 +1,235 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Kind of activity a track was recorded during
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ActivityType {
+    Bike,
+    EBike,
+    VirtualRide,
+    Run,
+    Walk,
+    Hike,
+    Ski,
+    Swim,
+    Row,
+    Kayak,
+}
+
+impl ActivityType {
+    /// Every activity type, in the order panels are drawn
+    pub const ALL: [Self; 10] = [
+        Self::Bike,
+        Self::EBike,
+        Self::VirtualRide,
+        Self::Run,
+        Self::Walk,
+        Self::Hike,
+        Self::Ski,
+        Self::Swim,
+        Self::Row,
+        Self::Kayak,
+    ];
+}
+
+impl fmt::Display for ActivityType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bike => write!(f, "Bike"),
+            Self::EBike => write!(f, "E-Bike"),
+            Self::VirtualRide => write!(f, "Virtual Ride"),
+            Self::Run => write!(f, "Run"),
+            Self::Walk => write!(f, "Walk"),
+            Self::Hike => write!(f, "Hike"),
+            Self::Ski => write!(f, "Ski"),
+            Self::Swim => write!(f, "Swim"),
+            Self::Row => write!(f, "Row"),
+            Self::Kayak => write!(f, "Kayak"),
+        }
+    }
+}
+
+impl FromStr for ActivityType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().replace(['-', '_', ' '], "").as_str() {
+            "bike" | "ride" => Ok(Self::Bike),
+            "ebike" => Ok(Self::EBike),
+            "virtualride" => Ok(Self::VirtualRide),
+            "run" => Ok(Self::Run),
+            "walk" => Ok(Self::Walk),
+            "hike" => Ok(Self::Hike),
+            "ski" => Ok(Self::Ski),
+            "swim" => Ok(Self::Swim),
+            "row" => Ok(Self::Row),
+            "kayak" | "paddle" => Ok(Self::Kayak),
+            _ => Err(format!(
+                "activity type must be bike, ebike, virtualride, run, walk, hike, ski, swim, row, or kayak, got {s}"
+            )),
+        }
+    }
+}
+
+/// Built-in activity names from GPX `<type>` elements (Strava codes and names, Garmin, Komoot, and `RideWithGPS`) and TCX `Sport` attributes, lowercase
+const DEFAULT_MAPPING: &[(&str, ActivityType)] = &[
+    // Strava numeric codes
+    ("1", ActivityType::Bike),
+    ("2", ActivityType::Ski),
+    ("3", ActivityType::Ski),
+    ("4", ActivityType::Hike),
+    ("7", ActivityType::Ski),
+    ("9", ActivityType::Run),
+    ("10", ActivityType::Walk),
+    ("16", ActivityType::Swim),
+    ("17", ActivityType::VirtualRide),
+    ("18", ActivityType::EBike),
+    ("20", ActivityType::Kayak),
+    ("21", ActivityType::Kayak),
+    ("22", ActivityType::Row),
+    // Strava names
+    ("ride", ActivityType::Bike),
+    ("mountainbikeride", ActivityType::Bike),
+    ("gravelride", ActivityType::Bike),
+    ("ebikeride", ActivityType::EBike),
+    ("emountainbikeride", ActivityType::EBike),
+    ("virtualride", ActivityType::VirtualRide),
+    ("run", ActivityType::Run),
+    ("trailrun", ActivityType::Run),
+    ("walk", ActivityType::Walk),
+    ("hike", ActivityType::Hike),
+    ("alpineski", ActivityType::Ski),
+    ("backcountryski", ActivityType::Ski),
+    ("nordicski", ActivityType::Ski),
+    ("swim", ActivityType::Swim),
+    ("rowing", ActivityType::Row),
+    ("kayaking", ActivityType::Kayak),
+    ("canoeing", ActivityType::Kayak),
+    ("standuppaddling", ActivityType::Kayak),
+    // Garmin and RideWithGPS
+    ("cycling", ActivityType::Bike),
+    ("biking", ActivityType::Bike),
+    ("road_biking", ActivityType::Bike),
+    ("mountain_biking", ActivityType::Bike),
+    ("gravel_cycling", ActivityType::Bike),
+    ("e_bike_fitness", ActivityType::EBike),
+    ("e_bike_mountain", ActivityType::EBike),
+    ("virtual_ride", ActivityType::VirtualRide),
+    ("indoor_cycling", ActivityType::VirtualRide),
+    ("running", ActivityType::Run),
+    ("trail_running", ActivityType::Run),
+    ("walking", ActivityType::Walk),
+    ("hiking", ActivityType::Hike),
+    ("resort_skiing_snowboarding", ActivityType::Ski),
+    ("backcountry_skiing", ActivityType::Ski),
+    ("cross_country_skiing", ActivityType::Ski),
+    ("swimming", ActivityType::Swim),
+    ("open_water_swimming", ActivityType::Swim),
+    ("stand_up_paddleboarding", ActivityType::Kayak),
+    // Komoot
+    ("touringbicycle", ActivityType::Bike),
+    ("racebike", ActivityType::Bike),
+    ("mtb", ActivityType::Bike),
+    ("mtb_easy", ActivityType::Bike),
+    ("mtb_advanced", ActivityType::Bike),
+    ("e_touringbicycle", ActivityType::EBike),
+    ("e_racebike", ActivityType::EBike),
+    ("e_mtb", ActivityType::EBike),
+    ("e_mtb_easy", ActivityType::EBike),
+    ("e_mtb_advanced", ActivityType::EBike),
+    ("jogging", ActivityType::Run),
+    ("mountaineering", ActivityType::Hike),
+    ("skitour", ActivityType::Ski),
+    ("nordic", ActivityType::Ski),
+    // TCX sports, where walks are usually recorded as Other
+    ("other", ActivityType::Walk),
+];
+
+/// Table from activity names in files to activity types, checking `custom` entries before the built-in ones
+#[derive(Default)]
+pub struct Mapping {
+    /// lowercase names
+    pub custom: Vec<(String, ActivityType)>,
+}
+
+impl Mapping {
+    #[must_use]
+    /// Activity type of the GPX `<type>` or TCX `Sport` `name` (ignoring case), if it's known
+    pub fn classify(&self, name: &str) -> Option<ActivityType> {
+        let name = name.trim().to_lowercase();
+        self.custom
+            .iter()
+            .map(|(custom, activity)| (custom.as_str(), *activity))
+            .chain(DEFAULT_MAPPING.iter().copied())
+            .find(|&(key, _)| key == name)
+            .map(|(_, activity)| activity)
+    }
+}
+
+/// Parses a custom mapping entry in the form of name=type (ex: SUP=kayak)
+pub fn parse_mapping(s: &str) -> Result<(String, ActivityType), String> {
+    let (name, activity) = s.rsplit_once('=').ok_or_else(|| {
+        format!("type mapping must be in form of name=type (ex: SUP=kayak), got {s}")
+    })?;
+    Ok((name.trim().to_lowercase(), activity.parse()?))
+}
+
+/// Activity types that tracks must and must not be, and how to read them from files
+pub struct TypeFilter<'a> {
+    /// only these types, or any type (including unknown types) if `None`
+    pub types: Option<&'a [ActivityType]>,
+    pub exclude: &'a [ActivityType],
+    pub mapping: &'a Mapping,
+}
+
+impl TypeFilter<'_> {
+    #[must_use]
+    /// Whether a track with the GPX `<type>` or TCX `Sport` `name` is one of the types and not excluded
+    pub fn allows(&self, name: &str) -> bool {
+        match self.mapping.classify(name) {
+            Some(activity) => {
+                self.types.is_none_or(|types| types.contains(&activity))
+                    && !self.exclude.contains(&activity)
+            }
+            None => self.types.is_none(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mapping_classify() {
+        let mut mapping = Mapping::default();
+        assert_eq!(mapping.classify("1"), Some(ActivityType::Bike));
+        assert_eq!(mapping.classify("Biking"), Some(ActivityType::Bike));
+        assert_eq!(mapping.classify("e_mtb"), Some(ActivityType::EBike));
+        assert_eq!(mapping.classify("SUP"), None);
+
+        mapping.custom.push(parse_mapping("SUP=kayak").unwrap());
+        mapping.custom.push(parse_mapping("other=hike").unwrap());
+        assert_eq!(mapping.classify("sup"), Some(ActivityType::Kayak));
+        assert_eq!(mapping.classify("Other"), Some(ActivityType::Hike));
+
+        let filter = TypeFilter {
+            types: Some(&[ActivityType::Kayak, ActivityType::Row]),
+            exclude: &[],
+            mapping: &mapping,
+        };
+        assert!(filter.allows("SUP"));
+        assert!(filter.allows("22"));
+        assert!(!filter.allows("9"));
+        let exclude = TypeFilter {
+            types: None,
+            exclude: &[ActivityType::VirtualRide],
+            mapping: &mapping,
+        };
+        assert!(!exclude.allows("VirtualRide"));
+        assert!(exclude.allows("Ride"));
+        assert!(exclude.allows("unknown"));
+        assert!(parse_mapping("SUP").is_err());
+        assert!(parse_mapping("SUP=boat").is_err());
+    }
+}
@@ -0,0 +1,356 @@
+use super::ramp::Ramp;
+use super::{blend, haversine, MapInfo, Point};
+use conv::prelude::*;
+use font8x8::{UnicodeFonts, BASIC_FONTS, LATIN_FONTS};
+use image::{Rgb, RgbImage};
+use std::str::FromStr;
+
+const GLYPH_SIZE: u32 = 8; // font8x8 glyphs are 8x8 pixels
+const TITLE_SCALE: u32 = 4;
+const LABEL_SCALE: u32 = 2;
+const MARGIN: u32 = 32; // distance between overlay boxes and the image edges
+const PADDING: u32 = 12; // distance between overlay contents and the edges of their box
+const FOREGROUND: Rgb<u8> = Rgb([255, 255, 255]);
+const BACKGROUND: Rgb<u8> = Rgb([0, 0, 0]);
+const BACKGROUND_ALPHA: f64 = 0.6;
+const METERS_PER_FOOT: f64 = 0.3048;
+const METERS_PER_MILE: f64 = 1609.344;
+
+#[derive(Clone, Copy)]
+pub enum Units {
+    Metric,
+    Imperial,
+}
+
+impl FromStr for Units {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "metric" => Ok(Self::Metric),
+            "imperial" => Ok(Self::Imperial),
+            _ => Err(format!("unknown units {s}, expected metric or imperial")),
+        }
+    }
+}
+
+#[must_use]
+/// Width in pixels of `text` when drawn by `draw_text` at `scale`
+pub fn text_width(text: &str, scale: u32) -> u32 {
+    u32::value_from(text.chars().count()).expect("text length must fit in u32") * GLYPH_SIZE * scale
+}
+
+/// Draws `text` with its top left corner at `x`, `y`, with each font pixel drawn as a `scale` x `scale` square
+/// Characters missing from the font are drawn as `?`
+pub fn draw_text(image: &mut RgbImage, x: u32, y: u32, text: &str, scale: u32, color: Rgb<u8>) {
+    let mut left = x;
+    for c in text.chars() {
+        let glyph = BASIC_FONTS
+            .get(c)
+            .or_else(|| LATIN_FONTS.get(c))
+            .or_else(|| BASIC_FONTS.get('?'))
+            .unwrap_or_default();
+        // each byte is a row of the glyph, with the least significant bit being the leftmost pixel
+        for (row, bits) in (0..GLYPH_SIZE).zip(glyph) {
+            for col in 0..GLYPH_SIZE {
+                if bits & (1 << col) != 0 {
+                    fill_rect(
+                        image,
+                        left + col * scale,
+                        y + row * scale,
+                        scale,
+                        scale,
+                        color,
+                        1.0,
+                    );
+                }
+            }
+        }
+        left += GLYPH_SIZE * scale;
+    }
+}
+
+#[must_use]
+/// Height in pixels of the area covered by `draw_title` for `title` and `subtitle`, including its margins, or 0 if neither is set
+pub fn title_height(title: Option<&str>, subtitle: Option<&str>) -> u32 {
+    let lines = title_lines(title, subtitle);
+    if lines.is_empty() {
+        0
+    } else {
+        title_box_height(&lines) + MARGIN * 2
+    }
+}
+
+/// Draws `title`, and `subtitle` in smaller text beneath it, in a box in the top left corner of `image`
+pub fn draw_title(image: &mut RgbImage, title: Option<&str>, subtitle: Option<&str>) {
+    let lines = title_lines(title, subtitle);
+    if lines.is_empty() {
+        return;
+    }
+
+    let width = lines
+        .iter()
+        .map(|&(text, scale)| text_width(text, scale))
+        .max()
+        .unwrap_or(0)
+        + PADDING * 2;
+    let height = title_box_height(&lines);
+    fill_rect(
+        image,
+        MARGIN,
+        MARGIN,
+        width,
+        height,
+        BACKGROUND,
+        BACKGROUND_ALPHA,
+    );
+
+    let mut y = MARGIN + PADDING;
+    for (text, scale) in lines {
+        draw_text(image, MARGIN + PADDING, y, text, scale, FOREGROUND);
+        y += GLYPH_SIZE * scale + PADDING;
+    }
+}
+
+/// Lines of text drawn by `draw_title`, with the scale of each
+fn title_lines<'a>(title: Option<&'a str>, subtitle: Option<&'a str>) -> Vec<(&'a str, u32)> {
+    title
+        .map(|t| (t, TITLE_SCALE))
+        .into_iter()
+        .chain(subtitle.map(|s| (s, LABEL_SCALE)))
+        .collect()
+}
+
+/// Height of the box drawn by `draw_title` around `lines`
+fn title_box_height(lines: &[(&str, u32)]) -> u32 {
+    lines
+        .iter()
+        .map(|&(_, scale)| GLYPH_SIZE * scale + PADDING)
+        .sum::<u32>()
+        + PADDING
+}
+
+/// Draws a scale bar in the bottom left corner of `image`
+/// The bar is the longest round distance (1, 2, or 5 times a power of ten in `units`) that fits in a fifth of the image width, measured at the center of the map
+pub fn draw_scale_bar(image: &mut RgbImage, map_info: &MapInfo, units: Units) {
+    // meters covered by a single horizontal pixel at the center of the map
+    let meters_per_pixel = haversine(
+        &map_info.center,
+        &Point {
+            lat: map_info.center.lat,
+            lng: map_info.center.lng + 1.0 / map_info.scale.lng.abs(),
+        },
+    );
+    let max_meters = meters_per_pixel * f64::from(image.width() / 5);
+
+    let (length, meters_per_unit, unit) = match units {
+        Units::Metric if max_meters >= 1000.0 => (nice_length(max_meters / 1000.0), 1000.0, "km"),
+        Units::Metric => (nice_length(max_meters), 1.0, "m"),
+        Units::Imperial if max_meters >= METERS_PER_MILE => (
+            nice_length(max_meters / METERS_PER_MILE),
+            METERS_PER_MILE,
+            "mi",
+        ),
+        Units::Imperial => (
+            nice_length(max_meters / METERS_PER_FOOT),
+            METERS_PER_FOOT,
+            "ft",
+        ),
+    };
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_sign_loss)]
+    let bar_width = (length * meters_per_unit / meters_per_pixel).round() as u32;
+    let bar_height = LABEL_SCALE * 4;
+    let label = format!("{length} {unit}");
+
+    let width = bar_width.max(text_width(&label, LABEL_SCALE)) + PADDING * 2;
+    let height = GLYPH_SIZE * LABEL_SCALE + bar_height + PADDING * 3;
+    let top = image.height().saturating_sub(MARGIN + height);
+    fill_rect(
+        image,
+        MARGIN,
+        top,
+        width,
+        height,
+        BACKGROUND,
+        BACKGROUND_ALPHA,
+    );
+    draw_text(
+        image,
+        MARGIN + PADDING,
+        top + PADDING,
+        &label,
+        LABEL_SCALE,
+        FOREGROUND,
+    );
+    fill_rect(
+        image,
+        MARGIN + PADDING,
+        top + PADDING * 2 + GLYPH_SIZE * LABEL_SCALE,
+        bar_width,
+        bar_height,
+        FOREGROUND,
+        1.0,
+    );
+}
+
+/// Draws a legend in the top right corner of `image` showing the track color ramp produced by `overlay_image`
+/// `single_step` and `min_alpha` are the per-track opacity step returned by and the minimum opacity passed to `overlay_image`
+pub fn draw_legend(image: &mut RgbImage, track_color: Rgb<u8>, min_alpha: f64, single_step: f64) {
+    // number of tracks on a pixel at which it becomes fully opaque
+    let max_tracks = (1.0 / single_step).ceil().max(1.0);
+
+    draw_ramp(image, "Tracks", "1", &format!("{max_tracks}+"), |t| {
+        // pixel opacity is linear in the number of tracks, from 1 track on the left to max_tracks on the right
+        let tracks = t.mul_add(max_tracks - 1.0, 1.0);
+        blend(
+            track_color,
+            BACKGROUND,
+            (tracks * single_step).clamp(min_alpha, 1.0),
+        )
+    });
+}
+
+/// Draws a legend in the top right corner of `image` showing the color ramp produced by `bins::draw_bins` for counts from 1 to `max` (on a logarithmic scale)
+pub fn draw_bin_legend(image: &mut RgbImage, caption: &str, ramp: &Ramp, max: u32) {
+    draw_ramp(image, caption, "1", &max.to_string(), |t| ramp.sample(t));
+}
+
+/// Draws a legend in the top right corner of `image` showing the color ramp produced by `diff::overlay_difference`, labeled with the `before` and `after` periods
+pub fn draw_difference_legend(image: &mut RgbImage, ramp: &Ramp, before: &str, after: &str) {
+    draw_ramp(image, "Change", before, after, |t| ramp.sample(t));
+}
+
+/// Draws a box in the top right corner of `image` containing `caption` above a horizontal ramp colored by `color_at` (called with 0 on the left through 1 on the right), labeled `low` on the left and `high` on the right
+fn draw_ramp(
+    image: &mut RgbImage,
+    caption: &str,
+    low: &str,
+    high: &str,
+    color_at: impl Fn(f64) -> Rgb<u8>,
+) {
+    // wide enough for the labels at either end to fit
+    let ramp_width = (image.width() / 6)
+        .max(text_width(low, LABEL_SCALE) + text_width(high, LABEL_SCALE) + PADDING);
+    let ramp_height = LABEL_SCALE * 8;
+
+    let width = ramp_width.max(text_width(caption, LABEL_SCALE)) + PADDING * 2;
+    let height = GLYPH_SIZE * LABEL_SCALE * 2 + ramp_height + PADDING * 4;
+    let left = image.width().saturating_sub(MARGIN + width);
+    fill_rect(
+        image,
+        left,
+        MARGIN,
+        width,
+        height,
+        BACKGROUND,
+        BACKGROUND_ALPHA,
+    );
+    draw_text(
+        image,
+        left + PADDING,
+        MARGIN + PADDING,
+        caption,
+        LABEL_SCALE,
+        FOREGROUND,
+    );
+
+    let ramp_top = MARGIN + PADDING * 2 + GLYPH_SIZE * LABEL_SCALE;
+    let ramp_steps = f64::from(ramp_width.max(2) - 1);
+    for i in 0..ramp_width {
+        fill_rect(
+            image,
+            left + PADDING + i,
+            ramp_top,
+            1,
+            ramp_height,
+            color_at(f64::from(i) / ramp_steps),
+            1.0,
+        );
+    }
+
+    let labels_top = ramp_top + ramp_height + PADDING;
+    draw_text(
+        image,
+        left + PADDING,
+        labels_top,
+        low,
+        LABEL_SCALE,
+        FOREGROUND,
+    );
+    draw_text(
+        image,
+        (left + PADDING + ramp_width).saturating_sub(text_width(high, LABEL_SCALE)),
+        labels_top,
+        high,
+        LABEL_SCALE,
+        FOREGROUND,
+    );
+}
+
+/// Draws `text` in small print in the bottom right corner of `image`
+pub fn draw_attribution(image: &mut RgbImage, text: &str) {
+    let width = text_width(text, LABEL_SCALE) + PADDING * 2;
+    let height = GLYPH_SIZE * LABEL_SCALE + PADDING * 2;
+    let left = image.width().saturating_sub(width);
+    let top = image.height().saturating_sub(height);
+    fill_rect(
+        image,
+        left,
+        top,
+        width,
+        height,
+        BACKGROUND,
+        BACKGROUND_ALPHA,
+    );
+    draw_text(
+        image,
+        left + PADDING,
+        top + PADDING,
+        text,
+        LABEL_SCALE,
+        FOREGROUND,
+    );
+}
+
+/// Blends `color` with opacity `alpha` onto the `width` x `height` rectangle with its top left corner at `x`, `y`, clipped to the bounds of `image`
+pub fn fill_rect(
+    image: &mut RgbImage,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    color: Rgb<u8>,
+    alpha: f64,
+) {
+    let x_end = x.saturating_add(width).min(image.width());
+    let y_end = y.saturating_add(height).min(image.height());
+    for curr_x in x..x_end {
+        for curr_y in y..y_end {
+            let pixel = image.get_pixel_mut(curr_x, curr_y);
+            *pixel = blend(color, *pixel, alpha);
+        }
+    }
+}
+
+/// Rounds `max` down to 1, 2, or 5 times a power of ten
+fn nice_length(max: f64) -> f64 {
+    let magnitude = 10_f64.powf(max.log10().floor());
+    [5.0, 2.0, 1.0]
+        .into_iter()
+        .map(|m| m * magnitude)
+        .find(|&length| length <= max)
+        .unwrap_or(magnitude)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nice_length_test() {
+        assert!((nice_length(7.3) - 5.0).abs() < f64::EPSILON);
+        assert!((nice_length(1999.0) - 1000.0).abs() < f64::EPSILON);
+        assert!((nice_length(25.0) - 20.0).abs() < f64::EPSILON);
+    }
+}
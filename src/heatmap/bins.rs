@@ -0,0 +1,190 @@
+use super::ramp::Ramp;
+use super::{blend, destination, wrap_lng, MapInfo, Point, TrkPt};
+use image::RgbImage;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+#[derive(Clone, Copy)]
+pub enum BinShape {
+    Hex,
+    Square,
+}
+
+impl FromStr for BinShape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "hex" => Ok(Self::Hex),
+            "square" => Ok(Self::Square),
+            _ => Err(format!("unknown bin shape {s}, expected hex or square")),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum BinCount {
+    /// Every track point in a bin is counted
+    Points,
+    /// Each track is counted at most once per bin
+    Visits,
+}
+
+impl FromStr for BinCount {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "points" => Ok(Self::Points),
+            "visits" => Ok(Self::Visits),
+            _ => Err(format!("unknown bin count {s}, expected points or visits")),
+        }
+    }
+}
+
+/// Counts of track points or visits aggregated into a grid of hexagons or squares of a fixed size in meters
+pub struct Bins {
+    shape: BinShape,
+    origin: Point,
+    /// degrees of latitude and longitude covered by one bin size at `origin`
+    step: Point,
+    pub counts: HashMap<(i64, i64), u32>,
+}
+
+impl Bins {
+    #[must_use]
+    /// Creates an empty grid of `shape` bins `size` meters across, with a bin centered on `origin`
+    /// The grid is laid out in degrees at `origin`, so bins will stretch the further they are from it
+    pub fn new(shape: BinShape, size: f64, origin: &Point) -> Self {
+        let north = destination(origin, 0.0, size);
+        let east = destination(origin, 90.0, size);
+        Self {
+            shape,
+            origin: *origin,
+            step: Point {
+                lat: north.lat - origin.lat,
+                lng: east.lng - origin.lng,
+            },
+            counts: HashMap::new(),
+        }
+    }
+
+    #[must_use]
+    /// Key of the bin containing `p`
+    pub fn key(&self, p: &Point) -> (i64, i64) {
+        // position relative to origin in units of bin size
+        let u = wrap_lng(p.lng - self.origin.lng) / self.step.lng;
+        let v = (p.lat - self.origin.lat) / self.step.lat;
+        match self.shape {
+            // squares are centered on whole units
+            BinShape::Square => round_key(u.round(), v.round()),
+            // pointy topped hexagons in axial coordinates, with adjacent centers 1 unit apart
+            BinShape::Hex => hex_round(u - v / 3_f64.sqrt(), 2.0 * v / 3_f64.sqrt()),
+        }
+    }
+
+    /// Adds the points in `trk_pts` to their bins, counting each point or only the first point of each track in a bin
+    pub fn add(&mut self, trk_pts: &[Vec<TrkPt>], count: BinCount) {
+        for v in trk_pts {
+            let mut visited = HashSet::new();
+            for pt in v {
+                let key = self.key(&pt.center);
+                if let BinCount::Visits = count {
+                    if !visited.insert(key) {
+                        continue;
+                    }
+                }
+                *self.counts.entry(key).or_insert(0) += 1;
+            }
+        }
+    }
+
+    #[must_use]
+    /// Highest count of any bin
+    pub fn max(&self) -> u32 {
+        self.counts.values().copied().max().unwrap_or(0)
+    }
+}
+
+/// Colors every pixel of `map_image` within a bin with `ramp`, from the start of the ramp for a count of 1 to its end for the highest count
+/// Counts are scaled logarithmically so sparse bins remain distinguishable next to dense ones
+pub fn draw_bins(
+    map_image: &mut RgbImage,
+    map_info: &MapInfo,
+    bins: &Bins,
+    ramp: &Ramp,
+    alpha: f64,
+) {
+    let max = f64::from(bins.max());
+    for (x, y, pixel) in map_image.enumerate_pixels_mut() {
+        // sample the center of the pixel
+        let p = map_info.point(f64::from(x) + 0.5, f64::from(y) + 0.5);
+        if let Some(&count) = bins.counts.get(&bins.key(&p)) {
+            let t = if max > 1.0 {
+                f64::from(count).ln() / max.ln()
+            } else {
+                1.0
+            };
+            *pixel = blend(ramp.sample(t), *pixel, alpha);
+        }
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+/// Converts whole number unit coordinates to a bin key
+fn round_key(u: f64, v: f64) -> (i64, i64) {
+    (u as i64, v as i64)
+}
+
+/// Rounds fractional axial hex coordinates to the key of the hexagon containing them
+fn hex_round(q: f64, r: f64) -> (i64, i64) {
+    let s = -q - r;
+    let (mut round_q, mut round_r, round_s) = (q.round(), r.round(), s.round());
+    let (diff_q, diff_r, diff_s) = (
+        (round_q - q).abs(),
+        (round_r - r).abs(),
+        (round_s - s).abs(),
+    );
+    // cube coordinates must sum to 0, so recompute whichever one was rounded the furthest
+    if diff_q > diff_r && diff_q > diff_s {
+        round_q = -round_r - round_s;
+    } else if diff_r > diff_s {
+        round_r = -round_q - round_s;
+    }
+    round_key(round_q, round_r)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trk(pts: &[(f64, f64)]) -> Vec<TrkPt> {
+        pts.iter()
+            .map(|&(lat, lng)| TrkPt {
+                center: Point { lat, lng },
+                ele: None,
+                time: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn hex_visits() {
+        let origin = Point {
+            lat: 30.25,
+            lng: -97.75,
+        };
+        let mut bins = Bins::new(BinShape::Hex, 500.0, &origin);
+        // two tracks passing twice through the hexagon at origin (the second point is ~100m away) and once through its eastern neighbor (~500m away)
+        let pts = trk(&[
+            (30.25, -97.75),
+            (30.2509, -97.75),
+            (30.25, -97.7448),
+            (30.25, -97.75),
+        ]);
+        bins.add(&[pts, trk(&[(30.25, -97.75)])], BinCount::Visits);
+        assert_eq!(bins.counts.get(&(0, 0)), Some(&2));
+        assert_eq!(bins.counts.get(&(1, 0)), Some(&1));
+        assert_eq!(bins.counts.len(), 2);
+    }
+}
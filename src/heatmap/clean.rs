@@ -0,0 +1,198 @@
+use super::activity::ActivityType;
+use super::{haversine, TrkPt};
+
+/// Built-in fastest plausible speeds in kilometers per hour
+const DEFAULT_MAX_SPEEDS: &[(ActivityType, f64)] = &[
+    (ActivityType::Bike, 120.0),
+    (ActivityType::EBike, 80.0),
+    (ActivityType::VirtualRide, 120.0),
+    (ActivityType::Run, 40.0),
+    (ActivityType::Walk, 20.0),
+    (ActivityType::Hike, 20.0),
+    (ActivityType::Ski, 160.0),
+    (ActivityType::Swim, 15.0),
+    (ActivityType::Row, 40.0),
+    (ActivityType::Kayak, 40.0),
+];
+
+/// Fastest plausible speed in kilometers per hour for tracks without a known activity type
+const DEFAULT_MAX_SPEED: f64 = 200.0;
+
+/// Most points in a row dropped as one glitch
+const MAX_GLITCH_POINTS: usize = 10;
+
+/// How GPS glitches are removed from tracks
+pub struct Cleaning {
+    /// fastest plausible speeds in kilometers per hour, overriding the built-in speeds for each activity type
+    pub max_speeds: Vec<(ActivityType, f64)>,
+    /// runs of points jumped to and back from further than this many meters, from points close to each other, are dropped
+    pub max_jump: f64,
+}
+
+impl Cleaning {
+    #[must_use]
+    /// Fastest plausible speed in meters per second for tracks of `activity`
+    pub fn max_speed(&self, activity: Option<ActivityType>) -> f64 {
+        let kph = activity.map_or(DEFAULT_MAX_SPEED, |activity| {
+            self.max_speeds
+                .iter()
+                .chain(DEFAULT_MAX_SPEEDS)
+                .find(|&&(a, _)| a == activity)
+                .map_or(DEFAULT_MAX_SPEED, |&(_, kph)| kph)
+        });
+        kph / 3.6
+    }
+
+    #[must_use]
+    /// Drops points of a track of `activity` at 0,0, short runs of points jumped away to from the track and back, and short runs of points reached from and left to the track faster than the activity's maximum speed
+    pub fn apply(&self, pts: Vec<TrkPt>, activity: Option<ActivityType>) -> Vec<TrkPt> {
+        let pts: Vec<TrkPt> = pts
+            .into_iter()
+            .filter(|pt| pt.center.lat != 0.0 || pt.center.lng != 0.0)
+            .collect();
+
+        let far = |a: &TrkPt, b: &TrkPt| haversine(&a.center, &b.center) > self.max_jump;
+        let jumps = glitches(&pts, far);
+        let pts = drop_where(pts, &jumps);
+
+        let max_speed = self.max_speed(activity);
+        let fast = |a: &TrkPt, b: &TrkPt| match (a.time, b.time) {
+            (Some(a_time), Some(b_time)) => {
+                #[allow(clippy::cast_precision_loss)]
+                let seconds = (b_time - a_time).num_milliseconds() as f64 / 1000.0;
+                seconds > 0.0 && haversine(&a.center, &b.center) / seconds > max_speed
+            }
+            _ => false,
+        };
+        let spikes = glitches(&pts, fast);
+        drop_where(pts, &spikes)
+    }
+}
+
+/// Which of `pts` are glitches away from the track: runs of up to `MAX_GLITCH_POINTS` points stepped into and out of apart from the track, where the points before and after the run aren't apart from each other
+/// (or a first or last point apart from its only neighbor while that neighbor isn't apart from its other neighbor)
+/// Tracks that are apart all along (like a whole track over a speed limit) are kept
+fn glitches(pts: &[TrkPt], apart: impl Fn(&TrkPt, &TrkPt) -> bool) -> Vec<bool> {
+    let mut drop = vec![false; pts.len()];
+    if pts.len() < 3 {
+        return drop;
+    }
+    let last = pts.len() - 1;
+
+    drop[0] = apart(&pts[0], &pts[1]) && !apart(&pts[1], &pts[2]);
+    let mut i = 1;
+    while i < last {
+        // a run starts where the track steps apart, and ends at the first step back that rejoins the track from before it
+        if !drop[i - 1] && apart(&pts[i - 1], &pts[i]) {
+            let end = (i..last)
+                .take(MAX_GLITCH_POINTS)
+                .find(|&j| apart(&pts[j], &pts[j + 1]) && !apart(&pts[i - 1], &pts[j + 1]));
+            if let Some(end) = end {
+                drop[i..=end].fill(true);
+                i = end + 1;
+                continue;
+            }
+        }
+        i += 1;
+    }
+    drop[last] = !drop[last - 1]
+        && apart(&pts[last - 1], &pts[last])
+        && !apart(&pts[last - 2], &pts[last - 1]);
+
+    drop
+}
+
+/// `pts` without the points where `drop` is true
+fn drop_where(pts: Vec<TrkPt>, drop: &[bool]) -> Vec<TrkPt> {
+    pts.into_iter()
+        .zip(drop)
+        .filter_map(|(pt, &drop)| (!drop).then_some(pt))
+        .collect()
+}
+
+/// Parses a maximum speed in the form of type=kph (ex: run=30)
+pub fn parse_max_speed(s: &str) -> Result<(ActivityType, f64), String> {
+    let error = || format!("max speed must be in form of type=kph (ex: run=30), got {s}");
+    let (activity, kph) = s.split_once('=').ok_or_else(error)?;
+    match kph.trim().parse::<f64>() {
+        Ok(kph) if kph > 0.0 => Ok((activity.parse()?, kph)),
+        _ => Err(error()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heatmap::Point;
+    use chrono::{DateTime, Duration, Utc};
+
+    #[test]
+    fn cleaning_apply() {
+        // ~111m north every 10 seconds (40 km/h), with a jump ~10km east and a point at 0,0
+        let start: DateTime<Utc> = "2024-01-01T00:00:00Z".parse().unwrap();
+        let mut pts: Vec<TrkPt> = (0..10)
+            .map(|i| TrkPt {
+                center: Point {
+                    lat: 30.0 + f64::from(i) * 0.001,
+                    lng: -97.0,
+                },
+                ele: None,
+                time: Some(start + Duration::seconds(i64::from(i) * 10)),
+            })
+            .collect();
+        pts[3].center.lng = -96.9;
+        pts[6].center = Point { lat: 0.0, lng: 0.0 };
+
+        let cleaning = Cleaning {
+            max_speeds: Vec::new(),
+            max_jump: 1000.0,
+        };
+        let cleaned = cleaning.apply(pts, Some(ActivityType::Bike));
+        assert_eq!(cleaned.len(), 8);
+        assert!(cleaned.iter().all(|pt| (pt.center.lng + 97.0).abs() < 1e-9));
+
+        // a steady ride just over the limit keeps its shape
+        let track = |count: i32| -> Vec<TrkPt> {
+            (0..count)
+                .map(|i| TrkPt {
+                    center: Point {
+                        lat: 30.0 + f64::from(i) * 0.001,
+                        lng: -97.0,
+                    },
+                    ele: None,
+                    time: Some(start + Duration::seconds(i64::from(i) * 10)),
+                })
+                .collect()
+        };
+        let cleaning = Cleaning {
+            max_speeds: vec![parse_max_speed("bike=30").unwrap()],
+            max_jump: 1000.0,
+        };
+        assert_eq!(
+            cleaning.apply(track(10), Some(ActivityType::Bike)).len(),
+            10
+        );
+
+        // but a single point ~500m off to the side (too close to be a jump) is ~180 km/h both ways
+        let cleaning = Cleaning {
+            max_speeds: Vec::new(),
+            max_jump: 1000.0,
+        };
+        let mut pts = track(10);
+        pts[4].center.lng = -96.995;
+        let cleaned = cleaning.apply(pts, Some(ActivityType::Bike));
+        assert_eq!(cleaned.len(), 9);
+        assert!(cleaned.iter().all(|pt| (pt.center.lng + 97.0).abs() < 1e-9));
+
+        // as is a drift of two points in a row, and a jump of two points in a row
+        let mut pts = track(10);
+        pts[4].center.lng = -96.995;
+        pts[5].center.lng = -96.995;
+        pts[7].center.lng = -96.9;
+        pts[8].center.lng = -96.9;
+        let cleaned = cleaning.apply(pts, Some(ActivityType::Bike));
+        assert_eq!(cleaned.len(), 6);
+        assert!(cleaned.iter().all(|pt| (pt.center.lng + 97.0).abs() < 1e-9));
+        assert!(parse_max_speed("bike=-1").is_err());
+    }
+}
@@ -0,0 +1,97 @@
+use super::{haversine, TrkPt};
+use std::ops::Range;
+
+/// Groups tracks whose first points are within `distance` meters of the first point of any other track in the group
+/// Sorts `trk_pts` so that the tracks of each group are consecutive, largest group first, and returns the groups with at least `min_tracks` tracks
+pub fn by_start(
+    trk_pts: &mut Vec<Vec<TrkPt>>,
+    distance: f64,
+    min_tracks: usize,
+) -> Vec<Range<usize>> {
+    let starts: Vec<_> = trk_pts
+        .iter()
+        .map(|v| v.first().map(|pt| &pt.center))
+        .collect();
+
+    // union-find of track indices, joining every pair of tracks that start close together
+    let mut parents: Vec<usize> = (0..trk_pts.len()).collect();
+    for (i, a) in starts.iter().enumerate() {
+        for (j, b) in starts.iter().enumerate().skip(i + 1) {
+            if let (Some(a), Some(b)) = (a, b) {
+                if haversine(a, b) <= distance {
+                    let (root_a, root_b) = (root(&mut parents, i), root(&mut parents, j));
+                    parents[root_b] = root_a;
+                }
+            }
+        }
+    }
+
+    let roots: Vec<usize> = (0..trk_pts.len()).map(|i| root(&mut parents, i)).collect();
+    let mut sizes = vec![0; trk_pts.len()];
+    for &r in &roots {
+        sizes[r] += 1;
+    }
+
+    // largest groups first, breaking ties by root so groups stay consecutive
+    let mut keyed: Vec<_> = roots.into_iter().zip(trk_pts.drain(..)).collect();
+    keyed.sort_by_key(|&(r, _)| (usize::MAX - sizes[r], r));
+
+    let mut clusters = Vec::new();
+    let mut first = 0;
+    for (r, v) in keyed {
+        trk_pts.push(v);
+        if trk_pts.len() == first + sizes[r] {
+            if sizes[r] >= min_tracks {
+                clusters.push(first..trk_pts.len());
+            }
+            first = trk_pts.len();
+        }
+    }
+
+    clusters
+}
+
+/// Root of the union-find tree containing `i`, compressing the path to it
+fn root(parents: &mut [usize], i: usize) -> usize {
+    let mut r = i;
+    while parents[r] != r {
+        r = parents[r];
+    }
+    let mut curr = i;
+    while parents[curr] != r {
+        let next = parents[curr];
+        parents[curr] = r;
+        curr = next;
+    }
+    r
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heatmap::Point;
+
+    fn trk(lat: f64, lng: f64) -> Vec<TrkPt> {
+        vec![TrkPt {
+            center: Point { lat, lng },
+            ele: None,
+            time: None,
+        }]
+    }
+
+    #[test]
+    fn clusters_by_start() {
+        // two tracks in Austin ~1km apart chained to a third, one in Paris, and one ~3km from the Austin chain
+        let mut trk_pts = vec![
+            trk(48.8566, 2.3522),
+            trk(30.25, -97.75),
+            trk(30.26, -97.75),
+            trk(30.27, -97.75),
+            trk(30.30, -97.75),
+        ];
+        let clusters = by_start(&mut trk_pts, 2000.0, 2);
+        assert_eq!(clusters, vec![0..3]);
+        assert!(trk_pts[..3].iter().all(|v| v[0].center.lng < 0.0));
+        assert_eq!(trk_pts.len(), 5);
+    }
+}
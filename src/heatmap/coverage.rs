@@ -0,0 +1,232 @@
+use super::osm::{cell, cells_near, Roads};
+use super::region::Region;
+use super::{density, segment_distance, GapRule, MapInfo, TrkPt};
+use image::{Rgb, RgbImage};
+use std::collections::HashMap;
+
+/// `highway` values of ways counted as streets (leaving out motorways, service roads, and paths)
+const STREET_HIGHWAYS: &[&str] = &[
+    "trunk",
+    "trunk_link",
+    "primary",
+    "primary_link",
+    "secondary",
+    "secondary_link",
+    "tertiary",
+    "tertiary_link",
+    "unclassified",
+    "residential",
+    "living_street",
+    "road",
+];
+
+/// Most meters between the points along streets that are checked for nearby tracks
+const SAMPLE_SPACING: f64 = 10.0;
+
+/// Which parts of the streets in an area have been traveled
+pub struct Coverage {
+    /// street segments (by index into `Roads::segments`) and whether each equal length piece of them was traveled
+    pub segments: Vec<(usize, Vec<bool>)>,
+    /// meters of streets
+    pub length: f64,
+    /// meters of streets traveled
+    pub covered: f64,
+}
+
+impl Coverage {
+    #[must_use]
+    /// Finds the pieces of the streets of `roads` (with midpoints inside `boundary`, or anywhere if it's empty) within `radius` meters of the lines of `trk_pts`
+    pub fn new(
+        roads: &Roads,
+        boundary: &[Region],
+        trk_pts: &[Vec<TrkPt>],
+        radius: f64,
+        gap: &GapRule,
+    ) -> Self {
+        // lines between consecutive connected points (by track and index of their first point) with bounding boxes touching each grid cell
+        let mut cells: HashMap<(i64, i64), Vec<(usize, usize)>> = HashMap::new();
+        for (t, pts) in trk_pts.iter().enumerate() {
+            for (i, pair) in pts.windows(2).enumerate() {
+                if !gap.connects(&pair[0], &pair[1]) {
+                    continue;
+                }
+                let (a, b) = (&pair[0].center, &pair[1].center);
+                for lat in cell(a.lat.min(b.lat))..=cell(a.lat.max(b.lat)) {
+                    for lng in cell(a.lng.min(b.lng))..=cell(a.lng.max(b.lng)) {
+                        cells.entry((lat, lng)).or_default().push((t, i));
+                    }
+                }
+            }
+        }
+        let traveled = |p: &super::Point| {
+            cells_near(p, radius).any(|key| {
+                cells.get(&key).is_some_and(|lines| {
+                    lines.iter().any(|&(t, i)| {
+                        let (a, b) = (&trk_pts[t][i].center, &trk_pts[t][i + 1].center);
+                        segment_distance(p, a, b) <= radius
+                    })
+                })
+            })
+        };
+
+        let mut coverage = Self {
+            segments: Vec::new(),
+            length: 0.0,
+            covered: 0.0,
+        };
+        for (index, segment) in roads.segments.iter().enumerate() {
+            if !STREET_HIGHWAYS.contains(&roads.highways[segment.way].as_str())
+                || !(boundary.is_empty()
+                    || boundary
+                        .iter()
+                        .any(|region| region.contains(&roads.along(index, 0.5))))
+            {
+                continue;
+            }
+            // pieces are traveled if the tracks pass near their midpoints
+            #[allow(clippy::cast_possible_truncation)]
+            #[allow(clippy::cast_sign_loss)]
+            let count = ((segment.length / SAMPLE_SPACING).ceil() as usize).max(1);
+            #[allow(clippy::cast_precision_loss)]
+            let pieces: Vec<bool> = (0..count)
+                .map(|k| traveled(&roads.along(index, (k as f64 + 0.5) / count as f64)))
+                .collect();
+            #[allow(clippy::cast_precision_loss)]
+            let piece_length = segment.length / count as f64;
+            coverage.length += segment.length;
+            #[allow(clippy::cast_precision_loss)]
+            let covered = pieces.iter().filter(|&&piece| piece).count() as f64 * piece_length;
+            coverage.covered += covered;
+            coverage.segments.push((index, pieces));
+        }
+        coverage
+    }
+
+    #[must_use]
+    /// Percent of the length of the streets traveled
+    pub fn percent(&self) -> f64 {
+        if self.length > 0.0 {
+            self.covered / self.length * 100.0
+        } else {
+            0.0
+        }
+    }
+
+    #[must_use]
+    /// Lines along the pieces of streets that weren't traveled
+    pub fn uncovered(&self, roads: &Roads) -> Vec<Vec<TrkPt>> {
+        let mut lines = Vec::new();
+        for (index, pieces) in &self.segments {
+            #[allow(clippy::cast_precision_loss)]
+            let count = pieces.len() as f64;
+            let mut first = None;
+            for (k, &piece) in pieces.iter().chain([&true]).enumerate() {
+                match (piece, first) {
+                    (false, None) => first = Some(k),
+                    (true, Some(start)) => {
+                        #[allow(clippy::cast_precision_loss)]
+                        lines.push(
+                            [start, k]
+                                .into_iter()
+                                .map(|k| TrkPt {
+                                    center: roads.along(*index, k as f64 / count),
+                                    ele: None,
+                                    time: None,
+                                })
+                                .collect(),
+                        );
+                        first = None;
+                    }
+                    _ => (),
+                }
+            }
+        }
+        lines
+    }
+
+    /// Draws the pieces of streets that weren't traveled onto `image` in `color`
+    pub fn draw_uncovered(
+        &self,
+        image: &mut RgbImage,
+        map_info: &MapInfo,
+        roads: &Roads,
+        color: Rgb<u8>,
+    ) {
+        let gap = GapRule {
+            seconds: f64::INFINITY,
+            meters: f64::INFINITY,
+        };
+        let factors = density(
+            map_info,
+            image.width(),
+            image.height(),
+            &self.uncovered(roads),
+            &gap,
+        );
+        for (x, column) in factors.iter().enumerate() {
+            for (y, &count) in column.iter().enumerate() {
+                if count > 0 {
+                    #[allow(clippy::cast_possible_truncation)]
+                    image.put_pixel(x as u32, y as u32, color);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heatmap::{osm, Point};
+
+    /// A residential street ~555m long running north, a footway beside it, and a primary road to the east
+    const XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<osm version="0.6">
+  <node id="1" lat="30.0" lon="-97.0"/>
+  <node id="2" lat="30.005" lon="-97.0"/>
+  <node id="3" lat="30.0" lon="-96.999"/>
+  <node id="4" lat="30.005" lon="-96.999"/>
+  <node id="5" lat="30.0" lon="-96.99"/>
+  <node id="6" lat="30.005" lon="-96.99"/>
+  <way id="10"><nd ref="1"/><nd ref="2"/><tag k="highway" v="residential"/></way>
+  <way id="11"><nd ref="3"/><nd ref="4"/><tag k="highway" v="footway"/></way>
+  <way id="12"><nd ref="5"/><nd ref="6"/><tag k="highway" v="primary"/></way>
+</osm>"#;
+
+    #[test]
+    fn streets_covered() {
+        let roads = osm::parse_xml(XML).unwrap();
+        // up the first half of the residential street
+        let trk_pts = vec![(0..=25)
+            .map(|i| TrkPt {
+                center: Point {
+                    lat: 30.0 + f64::from(i) * 0.0001,
+                    lng: -97.00005,
+                },
+                ele: None,
+                time: None,
+            })
+            .collect()];
+        let gap = GapRule::default();
+
+        let coverage = Coverage::new(&roads, &[], &trk_pts, 20.0, &gap);
+        assert_eq!(coverage.segments.len(), 2);
+        assert!((coverage.length - 1112.0).abs() < 1.0);
+        // ~278m traveled, plus the 20m radius past its end
+        assert!((coverage.covered - 298.0).abs() < 12.0);
+        assert!((coverage.percent() - 26.8).abs() < 1.5);
+        // the rest of the residential street and all of the primary road
+        assert_eq!(coverage.uncovered(&roads).len(), 2);
+
+        let boundary = [Region::Circle {
+            center: Point {
+                lat: 30.0025,
+                lng: -97.0,
+            },
+            radius: 500.0,
+        }];
+        let coverage = Coverage::new(&roads, &boundary, &trk_pts, 20.0, &gap);
+        assert_eq!(coverage.segments.len(), 1);
+        assert!((coverage.percent() - 53.7).abs() < 3.0);
+    }
+}
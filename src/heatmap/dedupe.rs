@@ -0,0 +1,102 @@
+use super::{haversine, segment_distance, time_range, TrkPt};
+use chrono::{DateTime, Utc};
+
+/// A track dropped as a duplicate of another
+pub struct Duplicate {
+    /// time of the first point of the dropped track
+    pub start: DateTime<Utc>,
+    /// number of points in the dropped track
+    pub points: usize,
+    /// number of points in the track kept instead
+    pub kept_points: usize,
+}
+
+#[must_use]
+/// Finds tracks whose time ranges overlap with a track with more points and whose Hausdorff distance from it is at most `distance` meters, like the same activity recorded by two devices
+/// Tracks without timestamps are never duplicates. Returns whether each track (in the order of `trk_pts`) should be dropped, and the dropped ones
+pub fn find_duplicates(trk_pts: &[Vec<TrkPt>], distance: f64) -> (Vec<bool>, Vec<Duplicate>) {
+    let ranges: Vec<_> = trk_pts
+        .iter()
+        .map(|v| time_range(std::slice::from_ref(v)))
+        .collect();
+
+    // compare each track against every kept track with more points
+    let mut order: Vec<usize> = (0..trk_pts.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(trk_pts[i].len()));
+    let mut kept: Vec<usize> = Vec::new();
+    let mut dropped = vec![false; trk_pts.len()];
+    let mut duplicates = Vec::new();
+    for i in order {
+        if let Some((start, end)) = ranges[i] {
+            let original = kept.iter().copied().find(|&k| {
+                ranges[k].is_some_and(|(k_start, k_end)| start <= k_end && k_start <= end)
+                    && within(&trk_pts[i], &trk_pts[k], distance)
+                    && within(&trk_pts[k], &trk_pts[i], distance)
+            });
+            if let Some(original) = original {
+                dropped[i] = true;
+                duplicates.push(Duplicate {
+                    start,
+                    points: trk_pts[i].len(),
+                    kept_points: trk_pts[original].len(),
+                });
+                continue;
+            }
+        }
+        kept.push(i);
+    }
+
+    duplicates.sort_by_key(|d| d.start);
+
+    (dropped, duplicates)
+}
+
+/// Whether every point of `a` is within `distance` meters of the line through `b` (the directed Hausdorff distance is at most `distance`)
+/// Measuring to the line instead of the points of `b` lets tracks recorded at different rates match
+fn within(a: &[TrkPt], b: &[TrkPt], distance: f64) -> bool {
+    a.iter().all(|pa| match b {
+        [pb] => haversine(&pa.center, &pb.center) <= distance,
+        _ => b
+            .windows(2)
+            .any(|pair| segment_distance(&pa.center, &pair[0].center, &pair[1].center) <= distance),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heatmap::Point;
+    use chrono::Duration;
+
+    fn trk(count: i32, lng: f64, start: &str) -> Vec<TrkPt> {
+        let start: DateTime<Utc> = start.parse().unwrap();
+        (0..count)
+            .map(|i| TrkPt {
+                center: Point {
+                    lat: 30.0 + f64::from(i) * 0.01 / f64::from(count - 1),
+                    lng,
+                },
+                ele: None,
+                time: Some(start + Duration::seconds(i64::from(i) * 10)),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn duplicates_dropped() {
+        let trk_pts = vec![
+            // a watch and a bike computer ~5m apart, recording the same ride
+            trk(10, -97.0, "2024-01-01T10:00:00Z"),
+            trk(20, -97.00005, "2024-01-01T10:00:30Z"),
+            // the same route on another day
+            trk(10, -97.0, "2024-01-02T10:00:00Z"),
+            // a different route at the same time
+            trk(10, -97.01, "2024-01-01T10:00:00Z"),
+        ];
+        let (dropped, duplicates) = find_duplicates(&trk_pts, 20.0);
+        assert_eq!(dropped, vec![true, false, false, false]);
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].points, 10);
+        assert_eq!(duplicates[0].kept_points, 20);
+    }
+}
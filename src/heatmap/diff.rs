@@ -0,0 +1,80 @@
+use super::ramp::Ramp;
+use super::{blend, single_step};
+use image::RgbImage;
+
+/// Number of pixels covered by tracks in only one of the two compared periods
+pub struct Changes {
+    /// pixels with tracks only in the before period
+    pub abandoned: usize,
+    /// pixels with tracks only in the after period
+    pub explored: usize,
+}
+
+#[must_use]
+/// Overlays the difference between pixel track counts `before` and `after` (as returned by `density` for the same map) on `map_image`
+/// Each grid is normalized as in `overlay_image` (with `factor` applied to each) before taking the difference, so a period with more activity doesn't dominate
+/// Pixels are colored by `ramp`, from its start where only `before` has tracks, through its middle where both are equal, to its end where only `after` has tracks
+/// Opacity is the size of the difference, but at least `min_alpha` for any pixel with tracks
+pub fn overlay_difference(
+    mut map_image: RgbImage,
+    before: &[Vec<u32>],
+    after: &[Vec<u32>],
+    ramp: &Ramp,
+    factor: f64,
+    min_alpha: f64,
+) -> (RgbImage, Changes) {
+    let before_step = single_step(&[before], factor);
+    let after_step = single_step(&[after], factor);
+    let mut changes = Changes {
+        abandoned: 0,
+        explored: 0,
+    };
+
+    #[allow(clippy::cast_possible_truncation)]
+    for (x, (before_row, after_row)) in before.iter().zip(after).enumerate() {
+        for (y, (&before_count, &after_count)) in before_row.iter().zip(after_row).enumerate() {
+            if before_count == 0 && after_count == 0 {
+                continue;
+            }
+            if after_count == 0 {
+                changes.abandoned += 1;
+            } else if before_count == 0 {
+                changes.explored += 1;
+            }
+
+            // -1 when only before has tracks through 1 when only after has tracks
+            let difference = (f64::from(after_count) * after_step).min(1.0)
+                - (f64::from(before_count) * before_step).min(1.0);
+            let alpha = difference.abs().clamp(min_alpha, 1.0);
+
+            let map_pixel = map_image.get_pixel_mut(x as u32, y as u32);
+            *map_pixel = blend(
+                ramp.sample(f64::midpoint(difference, 1.0)),
+                *map_pixel,
+                alpha,
+            );
+        }
+    }
+
+    (map_image, changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn difference_changes() {
+        let ramp: Ramp = "diverging".parse().unwrap();
+        // columns of 2 pixels: before only, after only, both, neither
+        let before = vec![vec![3, 0], vec![0, 0], vec![2, 0], vec![0, 0]];
+        let after = vec![vec![0, 0], vec![1, 0], vec![2, 0], vec![0, 0]];
+        let (image, changes) =
+            overlay_difference(RgbImage::new(4, 2), &before, &after, &ramp, 1.0, 0.5);
+        assert_eq!(changes.abandoned, 1);
+        assert_eq!(changes.explored, 1);
+        // equal counts blend toward the middle of the ramp, untouched pixels stay as they were
+        assert_ne!(image.get_pixel(2, 0), &image::Rgb([0, 0, 0]));
+        assert_eq!(image.get_pixel(3, 0), &image::Rgb([0, 0, 0]));
+    }
+}
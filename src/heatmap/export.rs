@@ -0,0 +1,212 @@
+use super::MapInfo;
+use conv::prelude::*;
+use simple_error::bail;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+// TIFF field types
+const SHORT: u16 = 3;
+const LONG: u16 = 4;
+const DOUBLE: u16 = 12;
+
+/// Writes pixel track counts `factors` (as returned by `density`, indexed by x and then y) to `path`, in a format chosen by its extension
+/// - csv: one `x,y,lat,lng,count` row for every pixel with a count greater than 0
+/// - npy: a uint32 array with one row per pixel row of the map, plus an ESRI world file (.wld) alongside it
+/// - tif/tiff: a float32 `GeoTIFF` in WGS 84 latitude and longitude
+pub fn export_grid(
+    path: &Path,
+    factors: &[Vec<u32>],
+    map_info: &MapInfo,
+) -> Result<(), Box<dyn Error>> {
+    match path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase)
+        .as_deref()
+    {
+        Some("csv") => write_csv(path, factors, map_info),
+        Some("npy") => {
+            write_npy(path, factors)?;
+            write_world_file(&path.with_extension("wld"), map_info)
+        }
+        Some("tif" | "tiff") => write_geotiff(path, factors, map_info),
+        _ => bail!(
+            "Unknown grid export format for {}, expected .csv, .npy, or .tif",
+            path.display()
+        ),
+    }
+}
+
+/// Writes every pixel with a count greater than 0 as a row of x, y, latitude and longitude (of the pixel center), and count
+fn write_csv(path: &Path, factors: &[Vec<u32>], map_info: &MapInfo) -> Result<(), Box<dyn Error>> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "x,y,lat,lng,count")?;
+    for (x, column) in factors.iter().enumerate() {
+        for (y, &count) in column.iter().enumerate() {
+            if count > 0 {
+                let center = map_info.point(f64::value_from(x)?, f64::value_from(y)?);
+                writeln!(writer, "{x},{y},{},{},{count}", center.lat, center.lng)?;
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes counts as a little endian uint32 `NumPy` array of shape (height, width)
+fn write_npy(path: &Path, factors: &[Vec<u32>]) -> Result<(), Box<dyn Error>> {
+    let (width, height) = dimensions(factors);
+    let mut header =
+        format!("{{'descr': '<u4', 'fortran_order': False, 'shape': ({height}, {width}), }}");
+    // magic string, version, and header length take 10 bytes, and the header is padded with spaces and a newline so the data is 64 byte aligned
+    let padding = 64 - (10 + header.len() + 1) % 64;
+    header.push_str(&" ".repeat(padding % 64));
+    header.push('\n');
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(b"\x93NUMPY\x01\x00")?;
+    writer.write_all(&u16::value_from(header.len())?.to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    for y in 0..height {
+        for column in factors {
+            writer.write_all(&column[y].to_le_bytes())?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes an ESRI world file mapping pixel coordinates to longitude and latitude
+fn write_world_file(path: &Path, map_info: &MapInfo) -> Result<(), Box<dyn Error>> {
+    // world files reference the center of the top left pixel
+    let center = map_info.point(0.0, 0.0);
+    fs::write(
+        path,
+        format!(
+            "{}\n0\n0\n{}\n{}\n{}\n",
+            1.0 / map_info.scale.lng,
+            1.0 / map_info.scale.lat,
+            center.lng,
+            center.lat
+        ),
+    )?;
+    Ok(())
+}
+
+/// Writes counts as a single strip, uncompressed, float32 `GeoTIFF` georeferenced with WGS 84 latitude and longitude
+fn write_geotiff(
+    path: &Path,
+    factors: &[Vec<u32>],
+    map_info: &MapInfo,
+) -> Result<(), Box<dyn Error>> {
+    let (width, height) = dimensions(factors);
+    let image_bytes = u32::value_from(width * height * 4)?;
+
+    // pixels cover a half pixel on each side of their center, so the tiepoint is the top left corner of the top left pixel
+    let corner = map_info.point(-0.5, -0.5);
+    let pixel_scale = [1.0 / map_info.scale.lng, -1.0 / map_info.scale.lat, 0.0];
+    let tiepoint = [0.0, 0.0, 0.0, corner.lng, corner.lat, 0.0];
+    let geo_keys: [u16; 16] = [
+        1, 1, 0, 3, // key directory version 1.1.0 with 3 keys
+        1024, 0, 1, 2, // GTModelTypeGeoKey = ModelTypeGeographic
+        1025, 0, 1, 1, // GTRasterTypeGeoKey = RasterPixelIsArea
+        2048, 0, 1, 4326, // GeographicTypeGeoKey = GCS_WGS_84
+    ];
+
+    // header, then the IFD, then values too large to fit in IFD entries, then the image data
+    let entries = 14;
+    let ifd_size = 2 + entries * 12 + 4;
+    let pixel_scale_offset = 8 + ifd_size;
+    let tiepoint_offset = pixel_scale_offset + 3 * 8;
+    let geo_keys_offset = tiepoint_offset + 6 * 8;
+    let image_offset = geo_keys_offset + 16 * 2;
+
+    let mut out = Vec::with_capacity(usize::value_from(image_offset + image_bytes)?);
+    out.extend_from_slice(b"II*\x00");
+    out.extend_from_slice(&8_u32.to_le_bytes());
+
+    out.extend_from_slice(&u16::value_from(entries)?.to_le_bytes());
+    let width = u32::value_from(width)?;
+    let height = u32::value_from(height)?;
+    for (tag, field_type, count, value) in [
+        (256, LONG, 1, width),                  // ImageWidth
+        (257, LONG, 1, height),                 // ImageLength
+        (258, SHORT, 1, 32),                    // BitsPerSample
+        (259, SHORT, 1, 1),                     // Compression = none
+        (262, SHORT, 1, 1),                     // PhotometricInterpretation = BlackIsZero
+        (273, LONG, 1, image_offset),           // StripOffsets
+        (277, SHORT, 1, 1),                     // SamplesPerPixel
+        (278, LONG, 1, height),                 // RowsPerStrip
+        (279, LONG, 1, image_bytes),            // StripByteCounts
+        (284, SHORT, 1, 1),                     // PlanarConfiguration = chunky
+        (339, SHORT, 1, 3),                     // SampleFormat = IEEE floating point
+        (33550, DOUBLE, 3, pixel_scale_offset), // ModelPixelScaleTag
+        (33922, DOUBLE, 6, tiepoint_offset),    // ModelTiepointTag
+        (34735, SHORT, 16, geo_keys_offset),    // GeoKeyDirectoryTag
+    ] {
+        out.extend_from_slice(&u16::to_le_bytes(tag));
+        out.extend_from_slice(&field_type.to_le_bytes());
+        out.extend_from_slice(&u32::to_le_bytes(count));
+        // SHORT values that fit in the entry are left aligned
+        if field_type == SHORT && count == 1 {
+            out.extend_from_slice(&u16::value_from(value)?.to_le_bytes());
+            out.extend_from_slice(&[0, 0]);
+        } else {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    // no further IFDs
+    out.extend_from_slice(&0_u32.to_le_bytes());
+
+    for value in pixel_scale.iter().chain(&tiepoint) {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    for key in geo_keys {
+        out.extend_from_slice(&key.to_le_bytes());
+    }
+    for y in 0..usize::value_from(height)? {
+        for column in factors {
+            out.extend_from_slice(&f32::value_from(column[y])?.to_le_bytes());
+        }
+    }
+
+    fs::write(path, out)?;
+    Ok(())
+}
+
+/// Width and height of a grid indexed by x and then y
+fn dimensions(factors: &[Vec<u32>]) -> (usize, usize) {
+    (factors.len(), factors.first().map_or(0, Vec::len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn npy_header() {
+        // unique per process so concurrent test runs don't share the file
+        let path = env::temp_dir().join(format!(
+            "heatmap_npy_header_test_{}.npy",
+            std::process::id()
+        ));
+        write_npy(&path, &[vec![1, 2, 3], vec![4, 5, 6]]).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let header_len = usize::from(u16::from_le_bytes([bytes[8], bytes[9]]));
+        let header = std::str::from_utf8(&bytes[10..10 + header_len]).unwrap();
+        assert_eq!((10 + header_len) % 64, 0);
+        assert!(header.contains("'shape': (3, 2)"));
+        assert!(header.ends_with('\n'));
+        // first row is the first y of every x column
+        let data: Vec<u32> = bytes[10 + header_len..]
+            .chunks(4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        assert_eq!(data, vec![1, 4, 2, 5, 3, 6]);
+    }
+}
@@ -1,49 +1,43 @@
+use super::parse_text;
 use chrono::{DateTime, Utc};
 use quick_xml::events::{BytesStart, Event};
 use quick_xml::Reader;
+use regex::Regex;
 use simple_error::bail;
 use std::error::Error;
 
 pub fn get_pts(
     mut reader: Reader<&[u8]>,
-    type_filters: &Option<Vec<super::ActivityType>>,
-    start: &Option<DateTime<Utc>>,
-    end: &Option<DateTime<Utc>>,
-) -> Result<Vec<super::TrkPt>, Box<dyn Error>> {
+    type_filter: Option<&super::activity::TypeFilter>,
+    exclude_name: Option<&Regex>,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+) -> Result<super::Track, Box<dyn Error>> {
     let mut buf = Vec::new();
 
-    let filter_strings = type_filters.as_ref().map(|fs| {
-        fs.iter()
-            .map(|f| match f {
-                super::ActivityType::Bike => "1",
-                super::ActivityType::Run => "9",
-                super::ActivityType::Walk => "10",
-            })
-            .collect()
-    });
-
-    let mut trk_pts = Vec::new();
+    let mut track = super::Track::default();
+    let mut metadata_time = None;
+    let mut invalid_time = false;
+    let mut warnings = Vec::new();
 
     loop {
         match reader.read_event(&mut buf) {
             Ok(Event::Start(ref e)) => match e.name() {
                 b"metadata" => {
-                    if start.is_some() || end.is_some() {
-                        if let Some(ref time) = parse_metadata(&mut reader, &mut buf)? {
-                            if let Some(start) = start {
-                                if time < start {
-                                    return Ok(Vec::new());
-                                }
-                            }
-                            if let Some(end) = end {
-                                if time > end {
-                                    return Ok(Vec::new());
-                                }
+                    if let Some(text) = parse_metadata(&mut reader, &mut buf)? {
+                        match text.trim().parse::<DateTime<Utc>>() {
+                            Ok(time) => metadata_time = Some(time),
+                            Err(e) => {
+                                warnings.push(format!("Invalid <metadata> time {text}: {e}"));
+                                invalid_time = true;
                             }
                         }
                     }
+                    if !super::in_period(metadata_time, invalid_time, start, end) {
+                        return Ok(super::Track::default());
+                    }
                 }
-                b"trk" => trk_pts = parse_trk(&mut reader, &mut buf, &filter_strings)?,
+                b"trk" => track = parse_trk(&mut reader, &mut buf, type_filter, exclude_name)?,
                 _ => (),
             },
             Ok(Event::Eof) => break,
@@ -54,13 +48,18 @@ pub fn get_pts(
         buf.clear();
     }
 
-    Ok(trk_pts)
+    track.time = metadata_time;
+    track.invalid_time = invalid_time;
+    warnings.append(&mut track.warnings);
+    track.warnings = warnings;
+    Ok(track)
 }
 
+/// Reads the unparsed `<time>` in `<metadata>`, if it has one
 fn parse_metadata(
     reader: &mut Reader<&[u8]>,
     buf: &mut Vec<u8>,
-) -> Result<Option<DateTime<Utc>>, Box<dyn Error>> {
+) -> Result<Option<String>, Box<dyn Error>> {
     let mut time = None;
 
     loop {
@@ -69,7 +68,7 @@ fn parse_metadata(
         match reader.read_event(buf) {
             Ok(Event::Start(ref e)) => {
                 if let b"time" = e.name() {
-                    time = parse_time(reader, buf)?;
+                    time = Some(parse_text(reader, buf, b"time")?);
                 }
             }
             Ok(Event::End(ref e)) => {
@@ -84,14 +83,17 @@ fn parse_metadata(
     }
 }
 
+/// Parses a point, or adds to `warnings` and returns `None` if it doesn't have coordinates
 fn parse_trkpt(
     reader: &mut Reader<&[u8]>,
     event: &BytesStart,
+    warnings: &mut Vec<String>,
 ) -> Result<Option<super::TrkPt>, Box<dyn Error>> {
     let mut buf = Vec::new();
 
     let mut lat: Option<f64> = None;
     let mut lng: Option<f64> = None;
+    let mut ele: Option<f64> = None;
     let mut time: Option<DateTime<Utc>> = None;
 
     // the <trkpt> tag has "lat" and "lon" attributes that we read and parse into floats
@@ -105,15 +107,15 @@ fn parse_trkpt(
 
     loop {
         match reader.read_event(&mut buf) {
-            Ok(Event::Start(ref e)) => {
-                if let b"time" = e.name() {
-                    time = parse_time(reader, &mut buf)?;
-                }
-            }
+            Ok(Event::Start(ref e)) => match e.name() {
+                b"ele" => ele = parse_text(reader, &mut buf, b"ele")?.trim().parse().ok(),
+                b"time" => time = parse_time(reader, &mut buf)?,
+                _ => (),
+            },
             Ok(Event::End(ref e)) => {
                 if let b"trkpt" = e.name() {
                     if lat.is_none() || lng.is_none() {
-                        eprintln!("Incomplete <Trackpoint>: {lat:?} {lng:?} {time:?}");
+                        warnings.push(format!("Incomplete <trkpt>: {lat:?} {lng:?} {time:?}"));
                         return Ok(None);
                     }
                     return Ok(Some(super::TrkPt {
@@ -121,6 +123,7 @@ fn parse_trkpt(
                             lat: lat.unwrap(),
                             lng: lng.unwrap(),
                         },
+                        ele,
                         time,
                     }));
                 }
@@ -137,28 +140,37 @@ fn parse_trkpt(
 fn parse_trk(
     reader: &mut Reader<&[u8]>,
     buf: &mut Vec<u8>,
-    filter_strings: &Option<Vec<&str>>,
-) -> Result<Vec<super::TrkPt>, Box<dyn Error>> {
-    let mut trk_pts = Vec::new();
+    type_filter: Option<&super::activity::TypeFilter>,
+    exclude_name: Option<&Regex>,
+) -> Result<super::Track, Box<dyn Error>> {
+    let mut track = super::Track::default();
 
     loop {
         buf.clear();
 
         match reader.read_event(buf) {
             Ok(Event::Start(ref e)) => match e.name() {
-                b"trkseg" => trk_pts = parse_trkseg(reader, buf)?,
+                b"trkseg" => track.pts = parse_trkseg(reader, buf, &mut track.warnings)?,
                 b"type" => {
-                    if filter_strings.is_some()
-                        && !type_check(reader, buf, filter_strings.as_ref().unwrap())?
-                    {
-                        return Ok(Vec::new());
+                    let activity = parse_text(reader, buf, b"type")?;
+                    // check that track type matches filter
+                    if type_filter.is_some_and(|type_filter| !type_filter.allows(&activity)) {
+                        return Ok(super::Track::default());
+                    }
+                    track.activity = Some(activity);
+                }
+                b"name" => {
+                    let name = parse_text(reader, buf, b"name")?;
+                    if exclude_name.is_some_and(|exclude_name| exclude_name.is_match(&name)) {
+                        return Ok(super::Track::default());
                     }
+                    track.name = Some(name);
                 }
                 _ => (),
             },
             Ok(Event::End(ref e)) => {
                 if let b"trk" = e.name() {
-                    return Ok(trk_pts);
+                    return Ok(track);
                 }
             }
             Ok(Event::Eof) => bail!("Hit EOF while in <trk>"),
@@ -171,6 +183,7 @@ fn parse_trk(
 fn parse_trkseg(
     reader: &mut Reader<&[u8]>,
     buf: &mut Vec<u8>,
+    warnings: &mut Vec<String>,
 ) -> Result<Vec<super::TrkPt>, Box<dyn Error>> {
     let mut trk_pts = Vec::new();
 
@@ -180,7 +193,7 @@ fn parse_trkseg(
         match reader.read_event(buf) {
             Ok(Event::Start(ref e)) => {
                 if let b"trkpt" = e.name() {
-                    if let Some(trkpt) = parse_trkpt(reader, e)? {
+                    if let Some(trkpt) = parse_trkpt(reader, e, warnings)? {
                         trk_pts.push(trkpt);
                     }
                 }
@@ -225,26 +238,3 @@ fn parse_time(
         }
     }
 }
-
-fn type_check(
-    reader: &mut Reader<&[u8]>,
-    buf: &mut Vec<u8>,
-    filter_strings: &[&str],
-) -> Result<bool, Box<dyn Error>> {
-    loop {
-        buf.clear();
-
-        match reader.read_event(buf) {
-            Ok(Event::Text(e)) => {
-                // check that segment type matches filter
-                return Ok(match e.unescape_and_decode(reader) {
-                    Ok(s) => filter_strings.contains(&&s[..]),
-                    Err(e) => return Err(Box::new(e)),
-                });
-            }
-            Ok(Event::Eof) => bail!("Hit EOF while checking <type>"),
-            Err(e) => bail!("Error at position {}: {:?}", reader.buffer_position(), e),
-            _ => (),
-        }
-    }
-}
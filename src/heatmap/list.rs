@@ -0,0 +1,332 @@
+use super::activity::Mapping;
+use super::metrics::Metrics;
+use super::stats::{align, Format};
+use super::{get_track, min_max, Filters, Point};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+
+/// What was read from a track file, or why it couldn't be
+pub struct Entry {
+    pub path: PathBuf,
+    /// GPX, TCX, or FIT, if the file was recognized
+    pub format: Option<String>,
+    /// activity type the file's type was mapped to, or the type as written if it isn't known
+    pub activity: Option<String>,
+    pub start: Option<DateTime<Utc>>,
+    /// seconds from the first to the last timestamp, if the track has timestamps
+    pub elapsed: Option<f64>,
+    /// meters
+    pub distance: f64,
+    pub points: usize,
+    /// southwest and northeast corners of the track (like `min_max`), if it has points
+    pub bounds: Option<(Point, Point)>,
+    /// problems reading the file, and why it wouldn't be loaded (if it wouldn't)
+    pub warnings: Vec<String>,
+}
+
+impl Entry {
+    /// An entry for `path` with nothing read from it yet
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            format: None,
+            activity: None,
+            start: None,
+            elapsed: None,
+            distance: 0.0,
+            points: 0,
+            bounds: None,
+            warnings: Vec::new(),
+        }
+    }
+}
+
+#[must_use]
+/// Reads every file in `file_list` (and in directories in it), without filtering or cleaning up the tracks
+/// Each entry notes whether the file would be left out when loaded with `filters`
+pub fn entries(file_list: &[PathBuf], filters: &Filters) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    for path in file_list {
+        if !path.is_dir() {
+            entries.push(entry(path, filters));
+            continue;
+        }
+        let dir = match fs::read_dir(path) {
+            Ok(dir) => dir,
+            Err(e) => {
+                let mut entry = Entry::new(path.clone());
+                entry.warnings.push(format!("Error reading directory: {e}"));
+                entries.push(entry);
+                continue;
+            }
+        };
+        let mut paths = Vec::new();
+        for dir_entry in dir {
+            match dir_entry {
+                Ok(dir_entry) => paths.push(dir_entry.path()),
+                Err(e) => {
+                    let mut entry = Entry::new(path.clone());
+                    entry
+                        .warnings
+                        .push(format!("Error reading directory entry: {e}"));
+                    entries.push(entry);
+                }
+            }
+        }
+        paths.sort();
+        // like when loading, excluded paths are only skipped inside directories
+        let (excluded, paths): (Vec<PathBuf>, Vec<PathBuf>) = paths.into_iter().partition(|path| {
+            filters
+                .exclude_paths
+                .iter()
+                .any(|pattern| pattern.matches_path(path))
+        });
+        for path in excluded {
+            let mut entry = Entry::new(path);
+            entry.warnings.push("Path is excluded".to_string());
+            entries.push(entry);
+        }
+        entries.append(&mut self::entries(&paths, filters));
+    }
+    entries
+}
+
+/// Reads the track in `path`, noting whether it would be left out when loaded with `filters`
+fn entry(path: &PathBuf, filters: &Filters) -> Entry {
+    let mut entry = Entry::new(path.clone());
+    if path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("fit"))
+    {
+        entry.format = Some("FIT".to_string());
+        entry
+            .warnings
+            .push("FIT files aren't supported yet".to_string());
+        return entry;
+    }
+
+    let mut track = match fs::read_to_string(path)
+        .map_err(Into::into)
+        .and_then(|contents| get_track(&contents, None, None, None, None))
+    {
+        Ok(track) => track,
+        Err(e) => {
+            entry.warnings.push(format!("Error reading file: {e}"));
+            return entry;
+        }
+    };
+    entry.format = track.format.map(|format| format.to_string());
+    entry.warnings.append(&mut track.warnings);
+    if let Some(activity) = &track.activity {
+        let default_mapping = Mapping::default();
+        let mapped = filters
+            .mapping
+            .unwrap_or(&default_mapping)
+            .classify(activity);
+        if mapped.is_none() {
+            entry
+                .warnings
+                .push(format!("Unknown activity type {activity}"));
+        }
+        entry.activity = Some(mapped.map_or_else(|| activity.clone(), |mapped| mapped.to_string()));
+    }
+    let metrics = Metrics::new(&track.pts);
+    entry.start = track.pts.iter().find_map(|pt| pt.time);
+    entry.elapsed = metrics.elapsed;
+    entry.distance = metrics.distance;
+    entry.points = track.pts.len();
+    if track.pts.is_empty() {
+        entry.warnings.push("No points".to_string());
+        return entry;
+    }
+    entry.bounds = Some(min_max([&track.pts]));
+
+    let loaded = filters.allows(&track) && !filters.select(filters.process(track).pts).is_empty();
+    if !loaded {
+        entry.warnings.push("Left out by the filters".to_string());
+    }
+    entry
+}
+
+#[must_use]
+/// Formats `entries` as an aligned table (in km), CSV, or a JSON array (both in meters and seconds)
+pub fn format(entries: &[Entry], format: Format) -> String {
+    let start = |entry: &Entry| {
+        entry.start.map_or_else(String::new, |start| {
+            start.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+        })
+    };
+    let bounds = |entry: &Entry| {
+        entry
+            .bounds
+            .as_ref()
+            .map_or_else(String::new, |(min, max)| {
+                format!(
+                    "{:.5},{:.5},{:.5},{:.5}",
+                    max.lat, max.lng, min.lat, min.lng
+                )
+            })
+    };
+    match format {
+        Format::Table => {
+            let cells: Vec<Vec<String>> = entries
+                .iter()
+                .map(|entry| {
+                    vec![
+                        entry.path.display().to_string(),
+                        entry.format.clone().unwrap_or_default(),
+                        entry.activity.clone().unwrap_or_default(),
+                        start(entry),
+                        entry.elapsed.map_or_else(String::new, duration),
+                        format!("{:.1}", entry.distance / 1000.0),
+                        entry.points.to_string(),
+                        bounds(entry),
+                        entry.warnings.join("; "),
+                    ]
+                })
+                .collect();
+            align(
+                &[
+                    "Path",
+                    "Format",
+                    "Activity",
+                    "Start",
+                    "Duration",
+                    "Distance (km)",
+                    "Points",
+                    "Box",
+                    "Warnings",
+                ],
+                &cells,
+                &[4, 5, 6],
+            )
+        }
+        Format::Csv => {
+            let mut csv = String::from(
+                "path,format,activity,start,duration_s,distance_m,points,box,warnings\n",
+            );
+            for entry in entries {
+                let fields = [
+                    entry.path.display().to_string(),
+                    entry.format.clone().unwrap_or_default(),
+                    entry.activity.clone().unwrap_or_default(),
+                    start(entry),
+                    entry
+                        .elapsed
+                        .map_or_else(String::new, |elapsed| format!("{elapsed:.0}")),
+                    format!("{:.0}", entry.distance),
+                    entry.points.to_string(),
+                    bounds(entry),
+                    entry.warnings.join("; "),
+                ];
+                let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+                writeln!(csv, "{}", fields.join(",")).expect("writing to a string can't fail");
+            }
+            csv
+        }
+        Format::Json => {
+            let entries: Vec<_> = entries
+                .iter()
+                .map(|entry| {
+                    json!({
+                        "path": entry.path.display().to_string(),
+                        "format": entry.format,
+                        "activity": entry.activity,
+                        "start": entry.start.map(|_| start(entry)),
+                        "duration_s": entry.elapsed.map(f64::round),
+                        "distance_m": entry.distance.round(),
+                        "points": entry.points,
+                        "box": entry.bounds.as_ref().map(|(min, max)| [max.lat, max.lng, min.lat, min.lng]),
+                        "warnings": entry.warnings,
+                    })
+                })
+                .collect();
+            let mut json = serde_json::to_string_pretty(&entries).expect("entries must serialize");
+            json.push('\n');
+            json
+        }
+    }
+}
+
+/// `seconds` as hours, minutes, and seconds (ex: 1:02:03)
+fn duration(seconds: f64) -> String {
+    #[allow(clippy::cast_possible_truncation)]
+    let seconds = seconds.round() as i64;
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// `field` quoted for CSV if it contains commas, quotes, or line breaks
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heatmap::ActivityType;
+
+    #[test]
+    fn files_listed() {
+        let dir = std::env::temp_dir().join(format!("heatmap-list-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("a.gpx"),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx><trk><type>cycling</type><trkseg>
+  <trkpt lat="30.0" lon="-97.0"><time>2021-06-01T12:00:00Z</time></trkpt>
+  <trkpt lon="-97.0"><time>2021-06-01T12:00:30Z</time></trkpt>
+  <trkpt lat="30.01" lon="-97.01"><time>2021-06-01T12:05:00Z</time></trkpt>
+</trkseg></trk></gpx>"#,
+        )
+        .unwrap();
+        fs::write(dir.join("b.fit"), [14, 16]).unwrap();
+        fs::write(dir.join("c.gpx"), "not a track").unwrap();
+
+        let run = [ActivityType::Run];
+        let entries = entries(
+            std::slice::from_ref(&dir),
+            &Filters {
+                types: Some(&run),
+                ..Filters::default()
+            },
+        );
+
+        assert_eq!(entries.len(), 3);
+        let gpx = &entries[0];
+        assert_eq!(gpx.format.as_deref(), Some("GPX"));
+        assert_eq!(gpx.activity.as_deref(), Some("Bike"));
+        assert_eq!(gpx.points, 2);
+        assert_eq!(gpx.elapsed, Some(300.0));
+        assert!((gpx.distance - 1471.0).abs() < 1.0);
+        assert_eq!(
+            gpx.warnings,
+            [
+                "Incomplete <trkpt>: None Some(-97.0) Some(2021-06-01T12:00:30Z)",
+                "Left out by the filters"
+            ]
+        );
+        assert_eq!(entries[1].format.as_deref(), Some("FIT"));
+        assert!(entries[2].format.is_none());
+        assert_eq!(entries[2].warnings.len(), 1);
+
+        let csv = format(&entries, Format::Csv);
+        assert_eq!(
+            csv.lines().nth(1).and_then(|line| line.split_once(',')).map(|(_, rest)| rest),
+            Some("GPX,Bike,2021-06-01T12:00:00Z,300,1471,2,\"30.01000,-97.00000,30.00000,-97.01000\",Incomplete <trkpt>: None Some(-97.0) Some(2021-06-01T12:00:30Z); Left out by the filters")
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
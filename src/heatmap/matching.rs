@@ -0,0 +1,374 @@
+use super::osm::Roads;
+use super::{haversine, GapRule, TrkPt};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+/// Standard deviation in meters of GPS error
+const SIGMA: f64 = 10.0;
+
+/// Scale in meters of the exponential distribution of differences between the lengths of routes and the lines between the track points they join
+const BETA: f64 = 20.0;
+
+/// Most road segments considered for each track point
+const MAX_CANDIDATES: usize = 8;
+
+/// How tracks are snapped onto roads with a hidden Markov model (Newson and Krumm), where each track point is matched to a point on a nearby road
+/// and consecutive matched points must be joined by a route about as long as the line between the track points
+pub struct Matcher<'a> {
+    pub roads: &'a Roads,
+    /// most meters from a track point to the road it's matched to, with points further than this from every road left as recorded
+    pub radius: f64,
+    /// track points broken by this are matched separately
+    pub gap: GapRule,
+}
+
+/// Point on a road a track point may be matched to
+struct Candidate {
+    segment: usize,
+    /// fraction of the way along `segment`
+    fraction: f64,
+    /// meters from the track point
+    distance: f64,
+}
+
+/// Shortest routes from a candidate to nodes, as the meters to each node and the node before it on its route (`None` for nodes at either end of the candidate's segment)
+type Routes = HashMap<usize, (f64, Option<usize>)>;
+
+impl Matcher<'_> {
+    #[must_use]
+    /// Replaces `pts` with their matched points on the roads and the road nodes on the routes between them, with timestamps interpolated along the routes
+    pub fn apply(&self, pts: &[TrkPt]) -> Vec<TrkPt> {
+        let mut matched = Vec::with_capacity(pts.len());
+        // consecutive connected points with candidates, by index into pts
+        let mut chain: Vec<(usize, Vec<Candidate>)> = Vec::new();
+        for (i, pt) in pts.iter().enumerate() {
+            if chain
+                .last()
+                .is_some_and(|&(prev, _)| !self.gap.connects(&pts[prev], pt))
+            {
+                self.match_chain(pts, &std::mem::take(&mut chain), &mut matched);
+            }
+            let candidates = self.candidates(pt);
+            if candidates.is_empty() {
+                self.match_chain(pts, &std::mem::take(&mut chain), &mut matched);
+                matched.push(pt.clone());
+            } else {
+                chain.push((i, candidates));
+            }
+        }
+        self.match_chain(pts, &chain, &mut matched);
+        matched
+    }
+
+    /// Points on roads within `radius` of `pt`, closest first
+    fn candidates(&self, pt: &TrkPt) -> Vec<Candidate> {
+        let mut candidates: Vec<Candidate> = self
+            .roads
+            .near(&pt.center, self.radius)
+            .into_iter()
+            .map(|segment| {
+                let (fraction, distance) = self.roads.project(segment, &pt.center);
+                Candidate {
+                    segment,
+                    fraction,
+                    distance,
+                }
+            })
+            .collect();
+        candidates.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        candidates.truncate(MAX_CANDIDATES);
+        candidates
+    }
+
+    /// Finds the most likely candidates of `chain` with the Viterbi algorithm and pushes them and the routes between them onto `matched`
+    /// Starts over wherever no route joins a point's candidates to the next point's
+    fn match_chain(
+        &self,
+        pts: &[TrkPt],
+        chain: &[(usize, Vec<Candidate>)],
+        matched: &mut Vec<TrkPt>,
+    ) {
+        let Some((_, first)) = chain.first() else {
+            return;
+        };
+        let emission = |candidate: &Candidate| -0.5 * (candidate.distance / SIGMA).powi(2);
+
+        // log probabilities of the most likely matches ending at each candidate, and the previous candidate of each
+        let mut scores: Vec<f64> = first.iter().map(emission).collect();
+        let mut previous: Vec<Vec<usize>> = vec![Vec::new()];
+        let mut start = 0;
+        for k in 1..chain.len() {
+            let ((prev_i, prev_candidates), (i, candidates)) = (&chain[k - 1], &chain[k]);
+            let straight = haversine(&pts[*prev_i].center, &pts[*i].center);
+            let limit = self.limit(straight);
+            let lengths: Vec<Vec<Option<f64>>> = prev_candidates
+                .iter()
+                .map(|a| {
+                    let routes = self.routes(a, limit);
+                    candidates
+                        .iter()
+                        .map(|b| self.route(a, b, &routes, limit).map(|(length, _)| length))
+                        .collect()
+                })
+                .collect();
+
+            let mut next_scores = Vec::with_capacity(candidates.len());
+            let mut next_previous = Vec::with_capacity(candidates.len());
+            for (j, candidate) in candidates.iter().enumerate() {
+                let best = (0..prev_candidates.len())
+                    .filter_map(|a| {
+                        lengths[a][j]
+                            .map(|length| (a, scores[a] - (length - straight).abs() / BETA))
+                    })
+                    .max_by(|x, y| x.1.total_cmp(&y.1));
+                let (a, score) = best.unwrap_or((0, f64::NEG_INFINITY));
+                next_scores.push(score + emission(candidate));
+                next_previous.push(a);
+            }
+
+            if next_scores.iter().all(|score| score.is_infinite()) {
+                self.push_matches(pts, &chain[start..k], &scores, &previous, matched);
+                start = k;
+                scores = candidates.iter().map(emission).collect();
+                previous = vec![Vec::new()];
+            } else {
+                scores = next_scores;
+                previous.push(next_previous);
+            }
+        }
+        self.push_matches(pts, &chain[start..], &scores, &previous, matched);
+    }
+
+    /// Backtracks from the best of the final `scores` through `previous` and pushes the matched points of `chain` and the routes between them onto `matched`
+    fn push_matches(
+        &self,
+        pts: &[TrkPt],
+        chain: &[(usize, Vec<Candidate>)],
+        scores: &[f64],
+        previous: &[Vec<usize>],
+        matched: &mut Vec<TrkPt>,
+    ) {
+        let Some(mut best) = (0..scores.len()).max_by(|&a, &b| scores[a].total_cmp(&scores[b]))
+        else {
+            return;
+        };
+        let mut chosen = vec![0; chain.len()];
+        for k in (0..chain.len()).rev() {
+            chosen[k] = best;
+            if k > 0 {
+                best = previous[k][best];
+            }
+        }
+
+        for k in 0..chain.len() {
+            let (pt_index, candidates) = &chain[k];
+            let to = &candidates[chosen[k]];
+            if k > 0 {
+                let (prev_index, prev_candidates) = &chain[k - 1];
+                let from = &prev_candidates[chosen[k - 1]];
+                let limit = self.limit(haversine(&pts[*prev_index].center, &pts[*pt_index].center));
+                let routes = self.routes(from, limit);
+                if let Some((length, nodes)) = self.route(from, to, &routes, limit) {
+                    // nodes along the route, with times interpolated by distance
+                    let segment = &self.roads.segments[from.segment];
+                    let mut along = match nodes.first() {
+                        Some(&node) if node == segment.from => from.fraction * segment.length,
+                        _ => (1.0 - from.fraction) * segment.length,
+                    };
+                    for (n, &node) in nodes.iter().enumerate() {
+                        if n > 0 {
+                            along +=
+                                haversine(&self.roads.nodes[nodes[n - 1]], &self.roads.nodes[node]);
+                        }
+                        let t = if length > 0.0 { along / length } else { 0.0 };
+                        matched.push(interpolate(
+                            &pts[*prev_index],
+                            &pts[*pt_index],
+                            t,
+                            self.roads.nodes[node],
+                        ));
+                    }
+                }
+            }
+            matched.push(TrkPt {
+                center: self.roads.along(to.segment, to.fraction),
+                ele: pts[*pt_index].ele,
+                time: pts[*pt_index].time,
+            });
+        }
+    }
+
+    /// Longest route considered between points `straight` meters apart
+    fn limit(&self, straight: f64) -> f64 {
+        2.0f64.mul_add(straight, 2.0 * self.radius)
+    }
+
+    /// Shortest routes from `a` to nodes within `limit` meters
+    fn routes(&self, a: &Candidate, limit: f64) -> Routes {
+        let segment = &self.roads.segments[a.segment];
+        let mut routes = Routes::new();
+        let mut heap = BinaryHeap::new();
+        for (node, length) in [
+            (segment.from, a.fraction * segment.length),
+            (segment.to, (1.0 - a.fraction) * segment.length),
+        ] {
+            // non-negative lengths are ordered by their bits
+            heap.push(Reverse((length.to_bits(), node, None)));
+        }
+        while let Some(Reverse((bits, node, prev))) = heap.pop() {
+            let length = f64::from_bits(bits);
+            if length > limit || routes.contains_key(&node) {
+                continue;
+            }
+            routes.insert(node, (length, prev));
+            for (segment, next) in self.roads.neighbors(node) {
+                if !routes.contains_key(&next) {
+                    let next_length = length + self.roads.segments[segment].length;
+                    heap.push(Reverse((next_length.to_bits(), next, Some(node))));
+                }
+            }
+        }
+        routes
+    }
+
+    /// Length of the shortest route from `a` to `b` within `limit` meters, and the road nodes along it
+    fn route(
+        &self,
+        a: &Candidate,
+        b: &Candidate,
+        routes: &Routes,
+        limit: f64,
+    ) -> Option<(f64, Vec<usize>)> {
+        let segment = &self.roads.segments[b.segment];
+        let direct = (a.segment == b.segment)
+            .then(|| ((a.fraction - b.fraction).abs() * segment.length, None));
+        let via = [
+            (segment.from, b.fraction * segment.length),
+            (segment.to, (1.0 - b.fraction) * segment.length),
+        ]
+        .into_iter()
+        .filter_map(|(node, rest)| {
+            routes
+                .get(&node)
+                .map(|&(length, _)| (length + rest, Some(node)))
+        });
+        let (length, end) = direct
+            .into_iter()
+            .chain(via)
+            .min_by(|x, y| x.0.total_cmp(&y.0))?;
+        if length > limit {
+            return None;
+        }
+
+        let mut nodes = Vec::new();
+        let mut node = end;
+        while let Some(n) = node {
+            nodes.push(n);
+            node = routes[&n].1;
+        }
+        nodes.reverse();
+        Some((length, nodes))
+    }
+}
+
+/// Point at `center` with the elevation and timestamp `t` of the way from `a` to `b`
+fn interpolate(a: &TrkPt, b: &TrkPt, t: f64, center: super::Point) -> TrkPt {
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_precision_loss)]
+    let time = a.time.zip(b.time).map(|(a_time, b_time)| {
+        let millis = (b_time - a_time).num_milliseconds() as f64 * t;
+        a_time + chrono::Duration::milliseconds(millis.round() as i64)
+    });
+    TrkPt {
+        center,
+        ele: a
+            .ele
+            .zip(b.ele)
+            .map(|(a_ele, b_ele)| t.mul_add(b_ele - a_ele, a_ele)),
+        time,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heatmap::osm;
+    use crate::heatmap::Point;
+    use chrono::{DateTime, Duration, Utc};
+
+    /// Two streets 100m apart running north, joined at their south ends
+    const XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<osm version="0.6">
+  <node id="1" lat="30.0" lon="-97.0"/>
+  <node id="2" lat="30.005" lon="-97.0"/>
+  <node id="3" lat="30.0" lon="-96.99896"/>
+  <node id="4" lat="30.005" lon="-96.99896"/>
+  <way id="10"><nd ref="1"/><nd ref="2"/><tag k="highway" v="residential"/></way>
+  <way id="11"><nd ref="3"/><nd ref="4"/><tag k="highway" v="residential"/></way>
+  <way id="12"><nd ref="1"/><nd ref="3"/><tag k="highway" v="residential"/></way>
+</osm>"#;
+
+    #[test]
+    fn tracks_matched() {
+        let roads = osm::parse_xml(XML).unwrap();
+        let matcher = Matcher {
+            roads: &roads,
+            radius: 30.0,
+            gap: GapRule::default(),
+        };
+
+        // north up the west street ~11m east of it every second, then a point ~45m from both streets
+        let start: DateTime<Utc> = "2024-01-01T00:00:00Z".parse().unwrap();
+        let mut pts: Vec<TrkPt> = (0..40)
+            .map(|i| TrkPt {
+                center: Point {
+                    lat: 30.0005 + f64::from(i) * 0.0001,
+                    lng: if i % 2 == 0 { -96.99988 } else { -97.00005 },
+                },
+                ele: None,
+                time: Some(start + Duration::seconds(i64::from(i))),
+            })
+            .collect();
+        pts.push(TrkPt {
+            center: Point {
+                lat: 30.0045,
+                lng: -96.99948,
+            },
+            ele: None,
+            time: Some(start + Duration::seconds(40)),
+        });
+
+        let snapped = matcher.apply(&pts);
+        assert_eq!(snapped.len(), 41);
+        assert!(snapped[..40]
+            .iter()
+            .all(|pt| (pt.center.lng + 97.0).abs() < 1e-9));
+        assert!((snapped[40].center.lng + 96.99948).abs() < 1e-9);
+
+        // a jump to the east street is routed along the south street
+        let snapped = matcher.apply(&[
+            TrkPt {
+                center: Point {
+                    lat: 30.0002,
+                    lng: -97.00005,
+                },
+                ele: None,
+                time: Some(start),
+            },
+            TrkPt {
+                center: Point {
+                    lat: 30.0002,
+                    lng: -96.99891,
+                },
+                ele: None,
+                time: Some(start + Duration::seconds(3)),
+            },
+        ]);
+        assert_eq!(snapped.len(), 4);
+        assert!((snapped[1].center.lat - 30.0).abs() < 1e-9);
+        // ~22m of the ~145m route
+        assert!(snapped[1].time > Some(start + Duration::milliseconds(400)));
+        assert!(snapped[1].time < Some(start + Duration::milliseconds(500)));
+        assert!((snapped[2].center.lng + 96.99896).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,192 @@
+use super::{haversine, TrkPt};
+use chrono::Duration;
+
+const MOVING_SPEED: f64 = 0.5; // meters per second, below which time between points isn't counted as moving
+const CLIMB_THRESHOLD: f64 = 3.0; // meters, of climbing from the last low point before it's counted, to ignore GPS elevation noise
+
+/// Totals of a single track
+pub struct Metrics {
+    /// meters along the track
+    pub distance: f64,
+    /// seconds from the first to the last timestamp, if the track has timestamps
+    pub elapsed: Option<f64>,
+    /// seconds spent moving faster than a slow walk, if the track has timestamps
+    pub moving: Option<f64>,
+    /// fastest speed between consecutive points in meters per second, if the track has timestamps
+    pub max_speed: Option<f64>,
+    /// meters climbed, if the track has elevations
+    pub elevation_gain: Option<f64>,
+}
+
+impl Metrics {
+    #[must_use]
+    /// Measures the track made of `pts`
+    pub fn new(pts: &[TrkPt]) -> Self {
+        let mut distance = 0.0;
+        let mut moving: Option<f64> = None;
+        let mut max_speed: Option<f64> = None;
+        for pair in pts.windows(2) {
+            let meters = haversine(&pair[0].center, &pair[1].center);
+            distance += meters;
+            if let (Some(prev_time), Some(time)) = (pair[0].time, pair[1].time) {
+                let seconds = seconds(time - prev_time);
+                if seconds <= 0.0 {
+                    continue;
+                }
+                let speed = meters / seconds;
+                let moving = moving.get_or_insert(0.0);
+                if speed >= MOVING_SPEED {
+                    *moving += seconds;
+                }
+                max_speed = Some(max_speed.map_or(speed, |max| max.max(speed)));
+            }
+        }
+
+        let first = pts.iter().find_map(|pt| pt.time);
+        let last = pts.iter().rev().find_map(|pt| pt.time);
+        let elapsed = first.zip(last).map(|(first, last)| seconds(last - first));
+
+        let mut elevation_gain: Option<f64> = None;
+        let mut low: Option<f64> = None;
+        for ele in pts.iter().filter_map(|pt| pt.ele) {
+            let gain = elevation_gain.get_or_insert(0.0);
+            match low {
+                Some(base) if ele - base >= CLIMB_THRESHOLD => {
+                    *gain += ele - base;
+                    low = Some(ele);
+                }
+                Some(base) if ele >= base => (),
+                _ => low = Some(ele),
+            }
+        }
+
+        Self {
+            distance,
+            elapsed,
+            moving,
+            max_speed,
+            elevation_gain,
+        }
+    }
+
+    #[must_use]
+    /// Average speed while moving in meters per second, if the track has timestamps and moved at all
+    pub fn average_speed(&self) -> Option<f64> {
+        self.moving
+            .filter(|&moving| moving > 0.0)
+            .map(|moving| self.distance / moving)
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+/// Length of `duration` in fractional seconds
+fn seconds(duration: Duration) -> f64 {
+    duration.num_milliseconds() as f64 / 1000.0
+}
+
+/// Allowed ranges of track metrics, with `None` being unlimited
+/// Tracks without timestamps are never within a limit on time or speed
+#[derive(Clone, Copy, Default)]
+pub struct Limits {
+    /// meters
+    pub distance: (Option<f64>, Option<f64>),
+    /// seconds
+    pub elapsed: (Option<f64>, Option<f64>),
+    /// seconds
+    pub moving: (Option<f64>, Option<f64>),
+    /// meters per second
+    pub average_speed: (Option<f64>, Option<f64>),
+    /// meters per second
+    pub max_speed: Option<f64>,
+}
+
+impl Limits {
+    #[must_use]
+    /// Whether every track is allowed
+    pub fn is_empty(&self) -> bool {
+        [
+            self.distance,
+            self.elapsed,
+            self.moving,
+            self.average_speed,
+            (None, self.max_speed),
+        ]
+        .iter()
+        .all(|&(min, max)| min.is_none() && max.is_none())
+    }
+
+    #[must_use]
+    /// Whether a track with `metrics` is within every limit
+    pub fn allows(&self, metrics: &Metrics) -> bool {
+        within(Some(metrics.distance), self.distance)
+            && within(metrics.elapsed, self.elapsed)
+            && within(metrics.moving, self.moving)
+            && within(metrics.average_speed(), self.average_speed)
+            && within(metrics.max_speed, (None, self.max_speed))
+    }
+}
+
+/// Whether `value` is between `min` and `max` (inclusive), which is always true if neither is set and never true if `value` is unknown but a limit is set
+fn within(value: Option<f64>, (min, max): (Option<f64>, Option<f64>)) -> bool {
+    match value {
+        Some(value) => min.is_none_or(|min| value >= min) && max.is_none_or(|max| value <= max),
+        None => min.is_none() && max.is_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heatmap::Point;
+    use chrono::{DateTime, Utc};
+
+    #[test]
+    fn metrics_limits() {
+        // ~111m north every 10 seconds, then a minute stopped in place
+        let start: DateTime<Utc> = "2020-01-01T00:00:00Z".parse().unwrap();
+        let mut pts: Vec<TrkPt> = (0..10)
+            .map(|i| TrkPt {
+                center: Point {
+                    lat: 30.0 + f64::from(i) * 0.001,
+                    lng: -97.0,
+                },
+                ele: None,
+                time: Some(start + Duration::seconds(i64::from(i) * 10)),
+            })
+            .collect();
+        pts.push(TrkPt {
+            center: Point {
+                lat: 30.009,
+                lng: -97.0,
+            },
+            ele: None,
+            time: Some(start + Duration::seconds(150)),
+        });
+
+        let metrics = Metrics::new(&pts);
+        assert!((metrics.distance - 1000.7).abs() < 1.0);
+        assert_eq!(metrics.elapsed, Some(150.0));
+        assert_eq!(metrics.moving, Some(90.0));
+        assert!((metrics.average_speed().unwrap() - 11.12).abs() < 0.01);
+        assert_eq!(metrics.elevation_gain, None);
+
+        // noise of a meter or two isn't counted as climbing
+        let eles = [100.0, 101.5, 100.5, 102.0, 105.0, 104.0, 110.0, 90.0, 92.0];
+        for (pt, ele) in pts.iter_mut().zip(eles) {
+            pt.ele = Some(ele);
+        }
+        assert_eq!(Metrics::new(&pts).elevation_gain, Some(11.0));
+
+        let limits = Limits {
+            distance: (Some(500.0), None),
+            ..Limits::default()
+        };
+        assert!(limits.allows(&metrics));
+        let limits = Limits {
+            max_speed: Some(10.0),
+            ..Limits::default()
+        };
+        assert!(!limits.allows(&metrics));
+        assert!(Limits::default().is_empty());
+    }
+}
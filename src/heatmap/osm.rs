@@ -0,0 +1,647 @@
+use super::{offset_meters, wrap_lng, Point};
+use flate2::read::ZlibDecoder;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use simple_error::bail;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+/// `highway` values of ways that can't be traveled along, like planned roads and bus stops
+const SKIPPED_HIGHWAYS: &[&str] = &[
+    "proposed",
+    "construction",
+    "abandoned",
+    "razed",
+    "disused",
+    "platform",
+    "bus_stop",
+    "elevator",
+    "emergency_bay",
+    "rest_area",
+    "services",
+];
+
+/// Size in degrees of the grid cells segments are indexed by
+const CELL_DEGREES: f64 = 0.005;
+
+/// Straight part of a way between two of its consecutive nodes
+pub struct Segment {
+    /// index into `Roads::nodes`
+    pub from: usize,
+    /// index into `Roads::nodes`
+    pub to: usize,
+    /// index into `Roads::highways`
+    pub way: usize,
+    /// meters from `from` to `to`
+    pub length: f64,
+}
+
+/// Road network read from an OSM extract, with every way that's traveled along split into segments
+pub struct Roads {
+    pub nodes: Vec<Point>,
+    /// `highway` value of each way
+    pub highways: Vec<String>,
+    pub segments: Vec<Segment>,
+    /// segments with bounding boxes touching each grid cell
+    cells: HashMap<(i64, i64), Vec<usize>>,
+    /// segments starting or ending at each node
+    edges: Vec<Vec<usize>>,
+}
+
+/// Way that's traveled along as read from an extract, before its nodes are looked up
+struct RawWay {
+    highway: String,
+    refs: Vec<i64>,
+}
+
+impl Roads {
+    /// Builds the network from node coordinates (lat, lng) by id and the ways referencing them
+    /// Segments between nodes missing from the extract (like at its edges) are skipped
+    fn new(coords: &HashMap<i64, (f64, f64)>, raw_ways: Vec<RawWay>) -> Self {
+        let mut roads = Self {
+            nodes: Vec::new(),
+            highways: Vec::new(),
+            segments: Vec::new(),
+            cells: HashMap::new(),
+            edges: Vec::new(),
+        };
+        let mut indexes: HashMap<i64, usize> = HashMap::new();
+        for raw_way in raw_ways {
+            let way = roads.highways.len();
+            let mut prev = None;
+            for id in raw_way.refs {
+                let node = coords.get(&id).map(|&(lat, lng)| {
+                    *indexes.entry(id).or_insert_with(|| {
+                        roads.nodes.push(Point { lat, lng });
+                        roads.edges.push(Vec::new());
+                        roads.nodes.len() - 1
+                    })
+                });
+                if let (Some(from), Some(to)) = (prev, node) {
+                    if from != to {
+                        roads.add_segment(from, to, way);
+                    }
+                }
+                prev = node;
+            }
+            roads.highways.push(raw_way.highway);
+        }
+        roads
+    }
+
+    fn add_segment(&mut self, from: usize, to: usize, way: usize) {
+        let segment = self.segments.len();
+        let (a, b) = (&self.nodes[from], &self.nodes[to]);
+        let (min_lat, max_lat) = (a.lat.min(b.lat), a.lat.max(b.lat));
+        let (min_lng, max_lng) = (a.lng.min(b.lng), a.lng.max(b.lng));
+        for lat in cell(min_lat)..=cell(max_lat) {
+            for lng in cell(min_lng)..=cell(max_lng) {
+                self.cells.entry((lat, lng)).or_default().push(segment);
+            }
+        }
+        self.segments.push(Segment {
+            from,
+            to,
+            way,
+            length: super::haversine(a, b),
+        });
+        self.edges[from].push(segment);
+        self.edges[to].push(segment);
+    }
+
+    #[must_use]
+    /// Segments within `radius` meters of `p`
+    pub fn near(&self, p: &Point, radius: f64) -> Vec<usize> {
+        let mut near: Vec<usize> = Vec::new();
+        for key in cells_near(p, radius) {
+            if let Some(segments) = self.cells.get(&key) {
+                near.extend(segments);
+            }
+        }
+        near.sort_unstable();
+        near.dedup();
+        near.retain(|&segment| self.project(segment, p).1 <= radius);
+        near
+    }
+
+    #[must_use]
+    /// Fraction of the way along `segment` of its closest point to `p`, and the meters between them
+    pub fn project(&self, segment: usize, p: &Point) -> (f64, f64) {
+        let segment = &self.segments[segment];
+        let (ax, ay) = offset_meters(p, &self.nodes[segment.from]);
+        let (bx, by) = offset_meters(p, &self.nodes[segment.to]);
+        let (dx, dy) = (bx - ax, by - ay);
+        let length = dx.mul_add(dx, dy * dy);
+        let t = if length > 0.0 {
+            (-(ax.mul_add(dx, ay * dy)) / length).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        (t, t.mul_add(dx, ax).hypot(t.mul_add(dy, ay)))
+    }
+
+    #[must_use]
+    /// Point `fraction` of the way along `segment`
+    pub fn along(&self, segment: usize, fraction: f64) -> Point {
+        let segment = &self.segments[segment];
+        let (a, b) = (&self.nodes[segment.from], &self.nodes[segment.to]);
+        Point {
+            lat: fraction.mul_add(b.lat - a.lat, a.lat),
+            lng: wrap_lng(fraction.mul_add(wrap_lng(b.lng - a.lng), a.lng)),
+        }
+    }
+
+    /// Segments starting or ending at `node`, with the node at their other end
+    pub fn neighbors(&self, node: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.edges[node].iter().map(move |&segment| {
+            let Segment { from, to, .. } = self.segments[segment];
+            (segment, if from == node { to } else { from })
+        })
+    }
+}
+
+#[must_use]
+#[allow(clippy::cast_possible_truncation)]
+/// Grid cell containing the latitude or longitude `degrees`, for indexing lines by area
+pub fn cell(degrees: f64) -> i64 {
+    (degrees / CELL_DEGREES).floor() as i64
+}
+
+/// Grid cells (latitude and longitude, like `cell`) that anything within `radius` meters of `p` could be in
+pub fn cells_near(p: &Point, radius: f64) -> impl Iterator<Item = (i64, i64)> {
+    let lat_degrees = radius / super::R.to_radians();
+    // longitude degrees shrink towards the poles, so cover more of them
+    let lng_degrees = lat_degrees / p.lat.to_radians().cos().max(0.01);
+    let lngs = cell(p.lng - lng_degrees)..=cell(p.lng + lng_degrees);
+    (cell(p.lat - lat_degrees)..=cell(p.lat + lat_degrees))
+        .flat_map(move |lat| lngs.clone().map(move |lng| (lat, lng)))
+}
+
+/// Reads the streets and paths of the OSM XML or PBF (if its name ends in .pbf) extract at `path`
+pub fn load(path: &Path) -> Result<Roads, Box<dyn Error>> {
+    if path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("pbf"))
+    {
+        parse_pbf(&fs::read(path)?)
+    } else {
+        parse_xml(&fs::read_to_string(path)?)
+    }
+}
+
+/// Way read from `tags` (key, value) and `refs` if it's traveled along
+fn raw_way<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+    tags: impl Iterator<Item = (K, V)>,
+    refs: Vec<i64>,
+) -> Option<RawWay> {
+    let highway = tags
+        .filter(|(key, _)| key.as_ref() == b"highway")
+        .map(|(_, value)| String::from_utf8_lossy(value.as_ref()).into_owned())
+        .next()?;
+    (!SKIPPED_HIGHWAYS.contains(&highway.as_str())).then_some(RawWay { highway, refs })
+}
+
+/// Parses an OSM XML extract
+pub fn parse_xml(contents: &str) -> Result<Roads, Box<dyn Error>> {
+    let mut reader = Reader::from_str(contents);
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+
+    let mut coords = HashMap::new();
+    let mut raw_ways = Vec::new();
+    loop {
+        match reader.read_event(&mut buf) {
+            Ok(Event::Start(ref e) | Event::Empty(ref e)) if e.name() == b"node" => {
+                let (id, lat, lng) = (attr(e, b"id")?, attr(e, b"lat")?, attr(e, b"lon")?);
+                if let (Some(id), Some(lat), Some(lng)) = (id, lat, lng) {
+                    coords.insert(id.parse()?, (lat.parse()?, lng.parse()?));
+                }
+            }
+            Ok(Event::Start(ref e)) if e.name() == b"way" => {
+                let mut refs = Vec::new();
+                let mut tags = Vec::new();
+                let mut way_buf = Vec::new();
+                loop {
+                    match reader.read_event(&mut way_buf) {
+                        Ok(Event::Start(ref e) | Event::Empty(ref e)) => match e.name() {
+                            b"nd" => {
+                                if let Some(node) = attr(e, b"ref")? {
+                                    refs.push(node.parse()?);
+                                }
+                            }
+                            b"tag" => {
+                                if let (Some(k), Some(v)) = (attr(e, b"k")?, attr(e, b"v")?) {
+                                    tags.push((k, v));
+                                }
+                            }
+                            _ => (),
+                        },
+                        Ok(Event::End(ref e)) if e.name() == b"way" => break,
+                        Ok(Event::Eof) => bail!("Hit EOF while in <way>"),
+                        Err(e) => bail!("Error at position {}: {:?}", reader.buffer_position(), e),
+                        _ => (),
+                    }
+                    way_buf.clear();
+                }
+                raw_ways.extend(raw_way(tags.into_iter(), refs));
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => bail!("Error at position {}: {:?}", reader.buffer_position(), e),
+            _ => (),
+        }
+        buf.clear();
+    }
+
+    Ok(Roads::new(&coords, raw_ways))
+}
+
+/// Value of the attribute `key` of `event`
+fn attr(event: &BytesStart, key: &[u8]) -> Result<Option<String>, Box<dyn Error>> {
+    for attr in event.attributes().flatten() {
+        if attr.key == key {
+            return Ok(Some(
+                std::str::from_utf8(&attr.unescaped_value()?)?.to_string(),
+            ));
+        }
+    }
+    Ok(None)
+}
+
+/// Value of a protobuf field, with fixed-size values skipped
+enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+}
+
+impl<'a> Value<'a> {
+    fn varint(self) -> Result<u64, Box<dyn Error>> {
+        match self {
+            Value::Varint(v) => Ok(v),
+            Value::Bytes(_) => bail!("Expected varint, got bytes"),
+        }
+    }
+
+    fn bytes(self) -> Result<&'a [u8], Box<dyn Error>> {
+        match self {
+            Value::Bytes(bytes) => Ok(bytes),
+            Value::Varint(_) => bail!("Expected bytes, got varint"),
+        }
+    }
+}
+
+/// Reads a varint from the start of `buf`, advancing it
+fn varint(buf: &mut &[u8]) -> Result<u64, Box<dyn Error>> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let Some((&byte, rest)) = buf.split_first() else {
+            bail!("Hit end of data while in varint");
+        };
+        *buf = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    bail!("Varint is longer than 64 bits")
+}
+
+/// Splits `len` bytes off the start of `buf`
+fn take<'a>(buf: &mut &'a [u8], len: usize) -> Result<&'a [u8], Box<dyn Error>> {
+    if buf.len() < len {
+        bail!("Expected {len} bytes, got {}", buf.len());
+    }
+    let (taken, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(taken)
+}
+
+/// Reads the next protobuf field number and value from `buf`, advancing it, or `None` at its end
+fn next_field<'a>(buf: &mut &'a [u8]) -> Result<Option<(u64, Value<'a>)>, Box<dyn Error>> {
+    while !buf.is_empty() {
+        let key = varint(buf)?;
+        match key & 0x7 {
+            0 => return Ok(Some((key >> 3, Value::Varint(varint(buf)?)))),
+            1 => drop(take(buf, 8)?),
+            2 => {
+                let len = usize::try_from(varint(buf)?)?;
+                return Ok(Some((key >> 3, Value::Bytes(take(buf, len)?))));
+            }
+            5 => drop(take(buf, 4)?),
+            wire_type => bail!("Unsupported protobuf wire type {}", wire_type),
+        }
+    }
+    Ok(None)
+}
+
+/// Varints packed into `bytes`
+fn packed(mut bytes: &[u8]) -> Result<Vec<u64>, Box<dyn Error>> {
+    let mut values = Vec::new();
+    while !bytes.is_empty() {
+        values.push(varint(&mut bytes)?);
+    }
+    Ok(values)
+}
+
+#[allow(clippy::cast_possible_wrap)]
+/// Decodes a zigzag-encoded signed varint
+fn zigzag(v: u64) -> i64 {
+    (v >> 1) as i64 ^ -((v & 1) as i64)
+}
+
+/// Decodes zigzag-encoded deltas packed into `bytes` into the values they add up to
+fn packed_deltas(bytes: &[u8]) -> Result<Vec<i64>, Box<dyn Error>> {
+    let mut value = 0_i64;
+    Ok(packed(bytes)?
+        .into_iter()
+        .map(|delta| {
+            value = value.wrapping_add(zigzag(delta));
+            value
+        })
+        .collect())
+}
+
+/// Parses an OSM PBF extract, a sequence of (possibly zlib compressed) blobs of nodes and ways
+pub fn parse_pbf(data: &[u8]) -> Result<Roads, Box<dyn Error>> {
+    let mut coords = HashMap::new();
+    let mut raw_ways = Vec::new();
+
+    let mut rest = data;
+    while !rest.is_empty() {
+        let header_len = u32::from_be_bytes(take(&mut rest, 4)?.try_into()?);
+        let mut header = take(&mut rest, usize::try_from(header_len)?)?;
+        let mut blob_type: &[u8] = &[];
+        let mut blob_len = 0;
+        while let Some((field, value)) = next_field(&mut header)? {
+            match field {
+                1 => blob_type = value.bytes()?,
+                3 => blob_len = usize::try_from(value.varint()?)?,
+                _ => (),
+            }
+        }
+        let mut blob = take(&mut rest, blob_len)?;
+        if blob_type != b"OSMData" {
+            continue;
+        }
+
+        let mut block = None;
+        while let Some((field, value)) = next_field(&mut blob)? {
+            match field {
+                1 => block = Some(value.bytes()?.to_vec()),
+                3 => {
+                    let mut decompressed = Vec::new();
+                    ZlibDecoder::new(value.bytes()?).read_to_end(&mut decompressed)?;
+                    block = Some(decompressed);
+                }
+                4..=7 => bail!("Unsupported PBF compression, only zlib is supported"),
+                _ => (),
+            }
+        }
+        if let Some(block) = block {
+            parse_block(&block, &mut coords, &mut raw_ways)?;
+        }
+    }
+
+    Ok(Roads::new(&coords, raw_ways))
+}
+
+/// Reads the nodes and ways of a PBF `PrimitiveBlock`
+fn parse_block(
+    mut block: &[u8],
+    coords: &mut HashMap<i64, (f64, f64)>,
+    raw_ways: &mut Vec<RawWay>,
+) -> Result<(), Box<dyn Error>> {
+    let mut strings: Vec<&[u8]> = Vec::new();
+    let mut groups = Vec::new();
+    let mut granularity = 100;
+    let mut lat_offset = 0;
+    let mut lng_offset = 0;
+    while let Some((field, value)) = next_field(&mut block)? {
+        match field {
+            1 => {
+                let mut table = value.bytes()?;
+                while let Some((field, value)) = next_field(&mut table)? {
+                    if field == 1 {
+                        strings.push(value.bytes()?);
+                    }
+                }
+            }
+            2 => groups.push(value.bytes()?),
+            17 => granularity = signed(value.varint()?),
+            19 => lat_offset = signed(value.varint()?),
+            20 => lng_offset = signed(value.varint()?),
+            _ => (),
+        }
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let degrees = |offset: i64, v: i64| 1e-9 * (offset + granularity * v) as f64;
+    let string = |index: u64| -> Result<&[u8], Box<dyn Error>> {
+        match usize::try_from(index)
+            .ok()
+            .and_then(|index| strings.get(index))
+        {
+            Some(s) => Ok(s),
+            None => bail!("String table index {} out of range", index),
+        }
+    };
+
+    for mut group in groups {
+        while let Some((field, value)) = next_field(&mut group)? {
+            let mut element = value.bytes()?;
+            match field {
+                // Node
+                1 => {
+                    let (mut id, mut lat, mut lng) = (0, 0, 0);
+                    while let Some((field, value)) = next_field(&mut element)? {
+                        match field {
+                            1 => id = zigzag(value.varint()?),
+                            8 => lat = zigzag(value.varint()?),
+                            9 => lng = zigzag(value.varint()?),
+                            _ => (),
+                        }
+                    }
+                    coords.insert(id, (degrees(lat_offset, lat), degrees(lng_offset, lng)));
+                }
+                // DenseNodes
+                2 => {
+                    let (mut ids, mut lats, mut lngs) = (Vec::new(), Vec::new(), Vec::new());
+                    while let Some((field, value)) = next_field(&mut element)? {
+                        match field {
+                            1 => ids = packed_deltas(value.bytes()?)?,
+                            8 => lats = packed_deltas(value.bytes()?)?,
+                            9 => lngs = packed_deltas(value.bytes()?)?,
+                            _ => (),
+                        }
+                    }
+                    for ((id, lat), lng) in ids.into_iter().zip(lats).zip(lngs) {
+                        coords.insert(id, (degrees(lat_offset, lat), degrees(lng_offset, lng)));
+                    }
+                }
+                // Way
+                3 => {
+                    let (mut keys, mut values, mut refs) = (Vec::new(), Vec::new(), Vec::new());
+                    while let Some((field, value)) = next_field(&mut element)? {
+                        match field {
+                            2 => keys = packed(value.bytes()?)?,
+                            3 => values = packed(value.bytes()?)?,
+                            8 => refs = packed_deltas(value.bytes()?)?,
+                            _ => (),
+                        }
+                    }
+                    let tags = keys
+                        .into_iter()
+                        .zip(values)
+                        .map(|(key, value)| Ok((string(key)?, string(value)?)))
+                        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+                    raw_ways.extend(raw_way(tags.into_iter(), refs));
+                }
+                _ => (),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::cast_possible_wrap)]
+/// Decodes a signed varint that isn't zigzag-encoded
+fn signed(v: u64) -> i64 {
+    v as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    const XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<osm version="0.6">
+  <node id="1" lat="30.0" lon="-97.0"/>
+  <node id="2" lat="30.001" lon="-97.0"/>
+  <node id="3" lat="30.002" lon="-97.0"><tag k="highway" v="crossing"/></node>
+  <way id="10">
+    <nd ref="1"/><nd ref="2"/><nd ref="3"/><nd ref="4"/>
+    <tag k="highway" v="residential"/>
+    <tag k="name" v="Main Street"/>
+  </way>
+  <way id="11">
+    <nd ref="1"/><nd ref="3"/>
+    <tag k="highway" v="proposed"/>
+  </way>
+  <way id="12"><nd ref="1"/><nd ref="2"/></way>
+</osm>"#;
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn encode_varint(mut v: u64, out: &mut Vec<u8>) {
+        while v >= 0x80 {
+            out.push((v & 0x7f) as u8 | 0x80);
+            v >>= 7;
+        }
+        out.push(v as u8);
+    }
+
+    fn encode_bytes(field: u64, bytes: &[u8], out: &mut Vec<u8>) {
+        encode_varint(field << 3 | 2, out);
+        encode_varint(bytes.len() as u64, out);
+        out.extend(bytes);
+    }
+
+    #[allow(clippy::cast_sign_loss)]
+    fn encode_packed(field: u64, values: &[i64], out: &mut Vec<u8>) {
+        let mut bytes = Vec::new();
+        let mut prev = 0;
+        for &v in values {
+            let delta = v - prev;
+            encode_varint(((delta << 1) ^ (delta >> 63)) as u64, &mut bytes);
+            prev = v;
+        }
+        encode_bytes(field, &bytes, out);
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    /// PBF extract with the same nodes and ways as `XML`, in a single zlib compressed blob
+    fn pbf() -> Vec<u8> {
+        let mut table = Vec::new();
+        for s in [
+            "",
+            "highway",
+            "residential",
+            "name",
+            "Main Street",
+            "proposed",
+        ] {
+            encode_bytes(1, s.as_bytes(), &mut table);
+        }
+        let mut dense = Vec::new();
+        encode_packed(1, &[1, 2, 3], &mut dense);
+        encode_packed(8, &[300_000_000, 300_010_000, 300_020_000], &mut dense);
+        encode_packed(9, &[-970_000_000; 3], &mut dense);
+        let mut group = Vec::new();
+        encode_bytes(2, &dense, &mut group);
+        for (id, refs, tags) in [
+            (10, vec![1, 2, 3, 4], vec![(1, 2), (3, 4)]),
+            (11, vec![1, 3], vec![(1, 5)]),
+        ] {
+            let mut way = Vec::new();
+            encode_varint(1 << 3, &mut way);
+            encode_varint(id, &mut way);
+            let mut keys = Vec::new();
+            let mut values = Vec::new();
+            for (k, v) in tags {
+                encode_varint(k, &mut keys);
+                encode_varint(v, &mut values);
+            }
+            encode_bytes(2, &keys, &mut way);
+            encode_bytes(3, &values, &mut way);
+            encode_packed(8, &refs, &mut way);
+            encode_bytes(3, &way, &mut group);
+        }
+        let mut block = Vec::new();
+        encode_bytes(1, &table, &mut block);
+        encode_bytes(2, &group, &mut block);
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&block).unwrap();
+        let mut blob = Vec::new();
+        encode_varint(2 << 3, &mut blob);
+        encode_varint(block.len() as u64, &mut blob);
+        encode_bytes(3, &encoder.finish().unwrap(), &mut blob);
+
+        let mut header = Vec::new();
+        encode_bytes(1, b"OSMData", &mut header);
+        encode_varint(3 << 3, &mut header);
+        encode_varint(blob.len() as u64, &mut header);
+
+        let mut data = (header.len() as u32).to_be_bytes().to_vec();
+        data.extend(header);
+        data.extend(blob);
+        data
+    }
+
+    #[test]
+    fn roads_parse() {
+        for roads in [parse_xml(XML).unwrap(), parse_pbf(&pbf()).unwrap()] {
+            // only Main Street, without the segment to the missing node 4
+            assert_eq!(roads.highways, vec!["residential"]);
+            assert_eq!(roads.nodes.len(), 3);
+            assert_eq!(roads.segments.len(), 2);
+            assert!((roads.segments[0].length - 111.2).abs() < 0.5);
+            assert!((roads.nodes[2].lat - 30.002).abs() < 1e-9);
+            assert_eq!(roads.neighbors(1).count(), 2);
+
+            let p = Point {
+                lat: 30.0005,
+                lng: -97.0001,
+            };
+            assert_eq!(roads.near(&p, 20.0), vec![0]);
+            assert!(roads.near(&p, 5.0).is_empty());
+            let (fraction, distance) = roads.project(0, &p);
+            assert!((fraction - 0.5).abs() < 1e-6);
+            assert!((distance - 9.6).abs() < 0.1);
+        }
+    }
+}
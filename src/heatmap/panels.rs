@@ -0,0 +1,181 @@
+use super::schedule::Zone;
+use super::{ActivityType, TrkPt};
+use image::{imageops, Rgb, RgbImage};
+use std::ops::Range;
+use std::str::FromStr;
+
+const GAP: u32 = 8; // pixels between panels
+const GAP_COLOR: Rgb<u8> = Rgb([0, 0, 0]);
+
+#[derive(Clone, Copy)]
+pub enum PanelBy {
+    Year,
+    Month,
+    Activity,
+}
+
+impl FromStr for PanelBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "year" => Ok(Self::Year),
+            "month" => Ok(Self::Month),
+            "activity" => Ok(Self::Activity),
+            _ => Err(format!(
+                "unknown panel grouping {s}, expected year, month, or activity"
+            )),
+        }
+    }
+}
+
+/// A labeled group of consecutive tracks drawn as one panel
+pub struct Panel {
+    pub label: String,
+    /// indices of the panel's tracks
+    pub tracks: Range<usize>,
+}
+
+/// Sorts `trk_pts` by their first timestamp in local time from `zone` and groups tracks whose local first timestamps are formatted to the same label by `format` (ex: %Y for years)
+/// Tracks without timestamps are sorted to the end and left out of every panel
+pub fn by_date(trk_pts: &mut [Vec<TrkPt>], format: &str, zone: Zone) -> Vec<Panel> {
+    let first_time = |v: &Vec<TrkPt>| {
+        v.iter()
+            .find_map(|pt| pt.time.map(|time| zone.local(time, &pt.center)))
+    };
+    trk_pts.sort_by_key(|v| {
+        let time = first_time(v);
+        (time.is_none(), time)
+    });
+
+    let mut panels: Vec<Panel> = Vec::new();
+    for (i, v) in trk_pts.iter().enumerate() {
+        let Some(time) = first_time(v) else {
+            break;
+        };
+        let label = time.format(format).to_string();
+        match panels.last_mut() {
+            Some(panel) if panel.label == label => panel.tracks.end = i + 1,
+            _ => panels.push(Panel {
+                label,
+                tracks: i..i + 1,
+            }),
+        }
+    }
+
+    panels
+}
+
+/// Groups `tracks` (each with the activity type of its file) by type, in the order of `type_filters` (or every activity type), with one panel per type that has tracks
+/// Tracks without a known type are left out of every panel. Returns the grouped tracks and the panels indexing them
+pub fn by_activity(
+    mut tracks: Vec<(Option<ActivityType>, Vec<TrkPt>)>,
+    type_filters: Option<&[ActivityType]>,
+) -> (Vec<Vec<TrkPt>>, Vec<Panel>) {
+    let mut trk_pts = Vec::new();
+    let mut panels = Vec::new();
+
+    for &activity in type_filters.unwrap_or(&ActivityType::ALL) {
+        let matching;
+        (matching, tracks) = tracks
+            .into_iter()
+            .partition(|&(track_activity, _)| track_activity == Some(activity));
+        if matching.is_empty() {
+            continue;
+        }
+        let first = trk_pts.len();
+        trk_pts.extend(matching.into_iter().map(|(_, pts)| pts));
+        panels.push(Panel {
+            label: activity.to_string(),
+            tracks: first..trk_pts.len(),
+        });
+    }
+
+    (trk_pts, panels)
+}
+
+#[must_use]
+/// Number of columns in a roughly square grid of `count` panels
+pub fn columns(count: usize) -> u32 {
+    let mut columns = 1;
+    while columns * columns < count {
+        columns += 1;
+    }
+    u32::try_from(columns).expect("panel columns must fit in u32")
+}
+
+#[must_use]
+/// Arranges equally sized `images` left to right, top to bottom in a grid `columns` wide, separated by gaps
+/// `header` pixels are left empty above the grid
+pub fn compose(images: &[RgbImage], columns: u32, header: u32) -> RgbImage {
+    let (width, height) = images.first().map_or((0, 0), RgbImage::dimensions);
+    let count = u32::try_from(images.len()).expect("panel count must fit in u32");
+    let rows = count.div_ceil(columns);
+
+    let mut grid = RgbImage::from_pixel(
+        columns * width + (columns - 1) * GAP,
+        header + rows * height + rows.saturating_sub(1) * GAP,
+        GAP_COLOR,
+    );
+    for (i, image) in (0..count).zip(images) {
+        let x = (i % columns) * (width + GAP);
+        let y = header + (i / columns) * (height + GAP);
+        imageops::replace(&mut grid, image, i64::from(x), i64::from(y));
+    }
+
+    grid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heatmap::Point;
+
+    fn trk(time: Option<&str>) -> Vec<TrkPt> {
+        vec![TrkPt {
+            center: Point { lat: 0.0, lng: 0.0 },
+            ele: None,
+            time: time.map(|t| t.parse().unwrap()),
+        }]
+    }
+
+    #[test]
+    fn panels_by_year() {
+        let mut trk_pts = vec![
+            trk(Some("2024-03-01T00:00:00Z")),
+            trk(None),
+            trk(Some("2023-05-01T00:00:00Z")),
+            trk(Some("2024-01-01T00:00:00Z")),
+        ];
+        let panels = by_date(&mut trk_pts, "%Y", Zone::Auto);
+        let panels: Vec<(&str, Range<usize>)> = panels
+            .iter()
+            .map(|p| (p.label.as_str(), p.tracks.clone()))
+            .collect();
+        assert_eq!(panels, vec![("2023", 0..1), ("2024", 1..3)]);
+        assert!(trk_pts[3][0].time.is_none());
+
+        // 23:30 on New Year's Eve in Chicago is already the next year in UTC
+        let mut trk_pts = vec![trk(Some("2024-01-01T05:30:00Z"))];
+        let chicago = "America/Chicago".parse().unwrap();
+        assert_eq!(by_date(&mut trk_pts, "%Y", chicago)[0].label, "2023");
+    }
+
+    #[test]
+    fn panels_by_activity() {
+        let tracks = vec![
+            (Some(ActivityType::Run), trk(Some("2024-03-01T00:00:00Z"))),
+            (None, trk(Some("2024-03-02T00:00:00Z"))),
+            (Some(ActivityType::Bike), trk(Some("2024-03-03T00:00:00Z"))),
+            (Some(ActivityType::Run), trk(Some("2024-03-04T00:00:00Z"))),
+        ];
+        let (trk_pts, panels) = by_activity(tracks, None);
+        let panels: Vec<(&str, Range<usize>)> = panels
+            .iter()
+            .map(|p| (p.label.as_str(), p.tracks.clone()))
+            .collect();
+        // the untyped track isn't drawn in any panel
+        assert_eq!(panels, vec![("Bike", 0..1), ("Run", 1..3)]);
+        assert_eq!(trk_pts.len(), 3);
+    }
+}
@@ -0,0 +1,142 @@
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, TimeZone, Utc};
+use std::str::FromStr;
+
+/// Span of time named by a date input, from `start` (inclusive) to `end` (exclusive)
+/// --start uses the start of the span and --end uses its end, so `--start 2023 --end 2023` covers all of 2023
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Period {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+impl FromStr for Period {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_at(s, Utc::now())
+    }
+}
+
+impl Period {
+    /// Parses `s` with relative inputs measured back from `now`
+    /// Accepts RFC 3339 date-times (ex: 2024-01-01T08:00:00Z), dates (2024-01-01), months (2024-06), years (2024),
+    /// lengths of time up to now in days, weeks, months, or years (30d, 2w, 6m, 1y),
+    /// and today, yesterday, this-month, last-month, this-year, last-year, or ytd (the same as this-year)
+    pub fn parse_at(s: &str, now: DateTime<Utc>) -> Result<Self, String> {
+        let s = s.trim();
+        let error = || {
+            format!("date must be a date (ex: 2024-01-01, 2024-06, or 2024), a length of time (ex: 30d, 2w, 6m, or 1y), today, yesterday, this-month, last-month, this-year, last-year, or ytd, got {s}")
+        };
+
+        if let Ok(time) = s.parse::<DateTime<Utc>>() {
+            return Ok(Self {
+                start: time,
+                end: time,
+            });
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            return Ok(Self::days(date, 1));
+        }
+        if let Some((year, month)) = s.split_once('-') {
+            if let (Ok(year), Ok(month)) = (year.parse(), month.parse()) {
+                return NaiveDate::from_ymd_opt(year, month, 1)
+                    .map(Self::month)
+                    .ok_or_else(error);
+            }
+        }
+        if s.len() == 4 {
+            if let Ok(year) = s.parse() {
+                return NaiveDate::from_ymd_opt(year, 1, 1)
+                    .map(Self::year)
+                    .ok_or_else(error);
+            }
+        }
+
+        let today = now.date_naive();
+        let this_month = today.with_day(1).expect("every month has a first day");
+        let this_year = this_month.with_month(1).expect("every year has a january");
+        match s.to_lowercase().as_str() {
+            "today" => return Ok(Self::days(today, 1)),
+            "yesterday" => return Ok(Self::days(today - Duration::days(1), 1)),
+            "this-month" => return Ok(Self::month(this_month)),
+            "last-month" => return Ok(Self::month(this_month - Months::new(1))),
+            "this-year" | "ytd" => return Ok(Self::year(this_year)),
+            "last-year" => return Ok(Self::year(this_year - Months::new(12))),
+            _ => (),
+        }
+
+        // length of time up to now
+        let (count, unit) = s.split_at(s.char_indices().last().map_or(0, |(i, _)| i));
+        let count: u32 = count.parse().map_err(|_| error())?;
+        let start = match unit.to_lowercase().as_str() {
+            "d" => now.checked_sub_signed(Duration::days(count.into())),
+            "w" => now.checked_sub_signed(Duration::weeks(count.into())),
+            "m" => now.checked_sub_months(Months::new(count)),
+            "y" => count
+                .checked_mul(12)
+                .and_then(|months| now.checked_sub_months(Months::new(months))),
+            _ => None,
+        }
+        .ok_or_else(error)?;
+        Ok(Self { start, end: now })
+    }
+
+    /// `count` days starting at `date`
+    fn days(date: NaiveDate, count: i64) -> Self {
+        let start = midnight(date);
+        Self {
+            start,
+            end: start + Duration::days(count),
+        }
+    }
+
+    /// Month starting at `first`
+    fn month(first: NaiveDate) -> Self {
+        Self {
+            start: midnight(first),
+            end: midnight(first + Months::new(1)),
+        }
+    }
+
+    /// Year starting at `first`
+    fn year(first: NaiveDate) -> Self {
+        Self {
+            start: midnight(first),
+            end: midnight(first + Months::new(12)),
+        }
+    }
+}
+
+/// Start of `date` in UTC
+fn midnight(date: NaiveDate) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).expect("midnight is valid"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn period_parse() {
+        let now: DateTime<Utc> = "2024-03-15T12:00:00Z".parse().unwrap();
+        let parse = |s: &str| Period::parse_at(s, now).unwrap();
+        let at = |s: &str| s.parse::<DateTime<Utc>>().unwrap();
+
+        assert_eq!(parse("2024-01-01").start, at("2024-01-01T00:00:00Z"));
+        assert_eq!(parse("2024-01-01").end, at("2024-01-02T00:00:00Z"));
+        assert_eq!(parse("2023-12").end, at("2024-01-01T00:00:00Z"));
+        assert_eq!(parse("2023").end, at("2024-01-01T00:00:00Z"));
+        assert_eq!(
+            parse("2024-01-01T08:00:00Z").end,
+            at("2024-01-01T08:00:00Z")
+        );
+        assert_eq!(parse("30d").start, at("2024-02-14T12:00:00Z"));
+        assert_eq!(parse("1y").start, at("2023-03-15T12:00:00Z"));
+        assert_eq!(parse("last-year").start, at("2023-01-01T00:00:00Z"));
+        assert_eq!(parse("ytd").start, at("2024-01-01T00:00:00Z"));
+        assert_eq!(parse("last-month").end, at("2024-03-01T00:00:00Z"));
+        for invalid in ["2024-13", "d", "30x", "5é", "next-year", "24"] {
+            assert!(Period::parse_at(invalid, now).is_err(), "{invalid}");
+        }
+    }
+}
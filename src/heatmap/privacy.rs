@@ -0,0 +1,89 @@
+use super::region::{split_where, Region};
+use super::{haversine, TrkPt};
+
+/// Parts of tracks hidden before rendering
+pub struct Privacy {
+    /// track points inside any of these are dropped
+    pub zones: Vec<Region>,
+    /// meters dropped from the start and end of every track
+    pub trim: f64,
+}
+
+impl Privacy {
+    #[must_use]
+    /// Whether `apply` would leave every track unchanged
+    pub fn is_empty(&self) -> bool {
+        self.zones.is_empty() && self.trim <= 0.0
+    }
+
+    #[must_use]
+    /// Trims the ends of each track in `trk_pts`, then drops points inside privacy zones
+    /// Tracks passing through a zone are split into separate tracks on either side, so they aren't joined across it
+    pub fn apply(&self, trk_pts: Vec<Vec<TrkPt>>) -> Vec<Vec<TrkPt>> {
+        if self.is_empty() {
+            return trk_pts;
+        }
+
+        trk_pts
+            .into_iter()
+            .flat_map(|v| {
+                split_where(trim(v, self.trim), |p| {
+                    !self.zones.iter().any(|zone| zone.contains(p))
+                })
+            })
+            .collect()
+    }
+}
+
+/// Drops the points of `pts` within `meters` (measured along the track) of its first or last point
+fn trim(pts: Vec<TrkPt>, meters: f64) -> Vec<TrkPt> {
+    if meters <= 0.0 {
+        return pts;
+    }
+
+    // distance along the track to each point
+    let mut along = Vec::with_capacity(pts.len());
+    let mut distance = 0.0;
+    for (i, pt) in pts.iter().enumerate() {
+        if i > 0 {
+            distance += haversine(&pts[i - 1].center, &pt.center);
+        }
+        along.push(distance);
+    }
+
+    pts.into_iter()
+        .zip(along)
+        .filter(|&(_, along)| along >= meters && distance - along >= meters)
+        .map(|(pt, _)| pt)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heatmap::Point;
+
+    #[test]
+    fn privacy_apply() {
+        // 21 points ~111m apart heading north, passing through a zone around the middle point
+        let pts = (0..21)
+            .map(|i| TrkPt {
+                center: Point {
+                    lat: 30.0 + f64::from(i) * 0.001,
+                    lng: -97.0,
+                },
+                ele: None,
+                time: None,
+            })
+            .collect();
+        let privacy = Privacy {
+            zones: vec!["30.01,-97,250".parse().unwrap()],
+            trim: 300.0,
+        };
+        let hidden = privacy.apply(vec![pts]);
+        let lens: Vec<usize> = hidden.iter().map(Vec::len).collect();
+        // 3 points trimmed from each end and 5 in the zone
+        assert_eq!(lens, vec![5, 5]);
+        assert!((hidden[0][0].center.lat - 30.003).abs() < 1e-9);
+    }
+}
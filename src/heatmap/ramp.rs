@@ -0,0 +1,79 @@
+use image::Rgb;
+use std::str::FromStr;
+
+/// Color ramp linearly interpolated between evenly spaced color stops
+pub struct Ramp(Vec<Rgb<u8>>);
+
+impl Ramp {
+    #[must_use]
+    /// Color at position `t` along the ramp, with `t` clamped to 0 (first stop) through 1 (last stop)
+    pub fn sample(&self, t: f64) -> Rgb<u8> {
+        let last = self.0.len() - 1;
+        #[allow(clippy::cast_precision_loss)]
+        let position = t.clamp(0.0, 1.0) * last as f64;
+        #[allow(clippy::cast_possible_truncation)]
+        #[allow(clippy::cast_sign_loss)]
+        let i = (position as usize).min(last - 1);
+        #[allow(clippy::cast_precision_loss)]
+        let frac = position - i as f64;
+        super::blend(self.0[i + 1], self.0[i], frac)
+    }
+}
+
+impl FromStr for Ramp {
+    type Err = String;
+
+    /// Parses a named ramp (heat, viridis, blues, or diverging) or a list of at least 2 r,g,b colors separated by semicolons
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let stops = match s {
+            "heat" => vec![
+                Rgb([255, 255, 178]),
+                Rgb([254, 204, 92]),
+                Rgb([253, 141, 60]),
+                Rgb([240, 59, 32]),
+                Rgb([189, 0, 38]),
+            ],
+            "viridis" => vec![
+                Rgb([68, 1, 84]),
+                Rgb([59, 82, 139]),
+                Rgb([33, 145, 140]),
+                Rgb([94, 201, 98]),
+                Rgb([253, 231, 37]),
+            ],
+            "blues" => vec![
+                Rgb([239, 243, 255]),
+                Rgb([189, 215, 231]),
+                Rgb([107, 174, 214]),
+                Rgb([49, 130, 189]),
+                Rgb([8, 81, 156]),
+            ],
+            "diverging" => vec![
+                Rgb([33, 102, 172]),
+                Rgb([103, 169, 207]),
+                Rgb([247, 247, 247]),
+                Rgb([239, 138, 98]),
+                Rgb([178, 24, 43]),
+            ],
+            _ => s.split(';').map(parse_color).collect::<Result<_, _>>()?,
+        };
+        if stops.len() < 2 {
+            return Err("ramp must have at least 2 colors".to_string());
+        }
+        Ok(Self(stops))
+    }
+}
+
+/// Parses a color in the form of r,g,b (ex: 0,0,255)
+pub fn parse_color(s: &str) -> Result<Rgb<u8>, String> {
+    let channels = s
+        .split(',')
+        .map(|c| c.trim().parse())
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| format!("color must be in form of r,g,b (ex: 0,0,255), got {s}"))?;
+    match channels[..] {
+        [r, g, b] => Ok(Rgb([r, g, b])),
+        _ => Err(format!(
+            "color must be in form of r,g,b (ex: 0,0,255), got {s}"
+        )),
+    }
+}
@@ -0,0 +1,226 @@
+use super::{haversine, wrap_lng, Point, TrkPt};
+use serde_json::Value;
+use simple_error::bail;
+use std::error::Error;
+use std::str::FromStr;
+
+/// An area on the map, either a circle or a polygon
+pub enum Region {
+    /// `radius` meters around `center`
+    Circle { center: Point, radius: f64 },
+    /// Vertices in order around the edge, with the last connected back to the first
+    Polygon(Vec<Point>),
+}
+
+impl Region {
+    #[must_use]
+    /// Whether `p` is inside the region
+    pub fn contains(&self, p: &Point) -> bool {
+        match self {
+            Self::Circle { center, radius } => haversine(center, p) <= *radius,
+            Self::Polygon(vertices) => {
+                // count crossings of the edges by a ray heading east from p, with longitudes relative to p so polygons can cross the antimeridian
+                let mut inside = false;
+                let Some(mut prev) = vertices.last() else {
+                    return false;
+                };
+                for vertex in vertices {
+                    if (vertex.lat > p.lat) != (prev.lat > p.lat) {
+                        let (lng, prev_lng) =
+                            (wrap_lng(vertex.lng - p.lng), wrap_lng(prev.lng - p.lng));
+                        let crossing =
+                            (p.lat - vertex.lat) / (prev.lat - vertex.lat) * (prev_lng - lng) + lng;
+                        if crossing > 0.0 {
+                            inside = !inside;
+                        }
+                    }
+                    prev = vertex;
+                }
+                inside
+            }
+        }
+    }
+}
+
+/// Regions that tracks must pass through to be loaded
+pub struct Geofence {
+    pub regions: Vec<Region>,
+    /// only keep the parts of tracks inside the regions, instead of whole tracks
+    pub clip: bool,
+}
+
+impl Geofence {
+    #[must_use]
+    /// Returns `pts` if any of its points are inside a region, or only the parts of it inside regions (as separate tracks) when clipping
+    pub fn apply(&self, pts: Vec<TrkPt>) -> Vec<Vec<TrkPt>> {
+        let inside = |p: &Point| self.regions.iter().any(|region| region.contains(p));
+        if self.clip {
+            split_where(pts, inside)
+        } else if pts.iter().any(|pt| inside(&pt.center)) {
+            vec![pts]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+#[must_use]
+/// Splits `pts` into separate tracks of consecutive points where `keep` is true, dropping the rest
+pub fn split_where(pts: Vec<TrkPt>, keep: impl Fn(&Point) -> bool) -> Vec<Vec<TrkPt>> {
+    let mut segments = Vec::new();
+    let mut segment = Vec::new();
+    for pt in pts {
+        if keep(&pt.center) {
+            segment.push(pt);
+        } else if !segment.is_empty() {
+            segments.push(std::mem::take(&mut segment));
+        }
+    }
+    if !segment.is_empty() {
+        segments.push(segment);
+    }
+
+    segments
+}
+
+/// Reads the outer ring of every `Polygon` and `MultiPolygon` geometry in `GeoJSON` `contents` as regions (holes are ignored)
+pub fn from_geojson(contents: &str) -> Result<Vec<Region>, Box<dyn Error>> {
+    let mut regions = Vec::new();
+    add_geojson(&serde_json::from_str(contents)?, &mut regions)?;
+    if regions.is_empty() {
+        bail!("No Polygon or MultiPolygon geometries found");
+    }
+    Ok(regions)
+}
+
+/// Adds the polygons in `GeoJSON` object `value` and any objects it contains to `regions`
+fn add_geojson(value: &Value, regions: &mut Vec<Region>) -> Result<(), Box<dyn Error>> {
+    let children = |key: &str| value[key].as_array().map_or(&[][..], Vec::as_slice);
+    match value["type"].as_str() {
+        Some("FeatureCollection") => {
+            for feature in children("features") {
+                add_geojson(feature, regions)?;
+            }
+        }
+        Some("Feature") => add_geojson(&value["geometry"], regions)?,
+        Some("GeometryCollection") => {
+            for geometry in children("geometries") {
+                add_geojson(geometry, regions)?;
+            }
+        }
+        Some("Polygon") => regions.push(polygon(&value["coordinates"][0])?),
+        Some("MultiPolygon") => {
+            for coordinates in children("coordinates") {
+                regions.push(polygon(&coordinates[0])?);
+            }
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+/// Reads a `GeoJSON` linear ring of [longitude, latitude] positions as a polygon
+fn polygon(ring: &Value) -> Result<Region, Box<dyn Error>> {
+    let Some(positions) = ring.as_array() else {
+        bail!("Expected an array of positions, got {}", ring);
+    };
+    let mut vertices = Vec::with_capacity(positions.len());
+    for position in positions {
+        match (position[0].as_f64(), position[1].as_f64()) {
+            (Some(lng), Some(lat)) => vertices.push(Point { lat, lng }),
+            _ => bail!(
+                "Expected a [longitude, latitude] position, got {}",
+                position
+            ),
+        }
+    }
+    if vertices.len() < 3 {
+        bail!("Polygon must have at least 3 positions, got {}", ring);
+    }
+    Ok(Region::Polygon(vertices))
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    /// Parses a circle as lat,lng,radius in meters (ex: 40.7812,-73.9665,500) or a polygon as at least 3 lat,lng points separated by semicolons
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains(';') {
+            let vertices = s
+                .split(';')
+                .map(str::parse)
+                .collect::<Result<Vec<Point>, _>>()?;
+            if vertices.len() < 3 {
+                return Err(format!("polygon must have at least 3 points, got {s}"));
+            }
+            return Ok(Self::Polygon(vertices));
+        }
+
+        let (center, radius) = s.rsplit_once(',').ok_or_else(|| {
+            format!("circle must be in form of lat,lng,radius (ex: 40.7812,-73.9665,500), got {s}")
+        })?;
+        let radius = match radius.trim().parse::<f64>() {
+            Ok(radius) if radius > 0.0 => radius,
+            _ => {
+                return Err(format!(
+                    "radius must be a number greater than 0, got {radius}"
+                ))
+            }
+        };
+        Ok(Self::Circle {
+            center: center.parse()?,
+            radius,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn geojson_polygons() {
+        let regions = from_geojson(
+            r#"{"type": "FeatureCollection", "features": [
+                {"type": "Feature", "properties": {}, "geometry": {"type": "Polygon", "coordinates": [[[-97.78, 30.31], [-97.77, 30.31], [-97.77, 30.32], [-97.78, 30.32], [-97.78, 30.31]]]}},
+                {"type": "Feature", "properties": {}, "geometry": {"type": "Point", "coordinates": [-97.7, 30.3]}}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(regions.len(), 1);
+        assert!(regions[0].contains(&Point {
+            lat: 30.315,
+            lng: -97.775
+        }));
+        assert!(from_geojson(r#"{"type": "Point", "coordinates": [0, 0]}"#).is_err());
+    }
+
+    #[test]
+    fn region_contains() {
+        let circle: Region = "30.25,-97.75,500".parse().unwrap();
+        assert!(circle.contains(&Point {
+            lat: 30.253,
+            lng: -97.75
+        }));
+        assert!(!circle.contains(&Point {
+            lat: 30.26,
+            lng: -97.75
+        }));
+
+        // a triangle crossing the antimeridian
+        let triangle: Region = "-16,179;-16,-179;-18,180".parse().unwrap();
+        assert!(triangle.contains(&Point {
+            lat: -16.5,
+            lng: -179.8
+        }));
+        assert!(triangle.contains(&Point {
+            lat: -16.5,
+            lng: 179.8
+        }));
+        assert!(!triangle.contains(&Point {
+            lat: -16.5,
+            lng: 178.5
+        }));
+        assert!("30.25,-97.75".parse::<Region>().is_err());
+    }
+}
@@ -0,0 +1,270 @@
+use super::{wrap_lng, Point, TrkPt};
+use chrono::{DateTime, Datelike, Duration, Month, NaiveDateTime, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+use std::str::FromStr;
+
+/// Timezone that local times are evaluated in
+#[derive(Clone, Copy, Default)]
+pub enum Zone {
+    /// Named IANA timezone (ex: America/Chicago)
+    Named(Tz),
+    /// Offset of a whole number of hours from UTC based on the longitude of the track (ignoring daylight saving time and political boundaries)
+    #[default]
+    Auto,
+}
+
+impl FromStr for Zone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(Self::Auto);
+        }
+        s.parse().map(Self::Named).map_err(|_| {
+            format!("unknown timezone {s}, expected auto or a name like America/Chicago")
+        })
+    }
+}
+
+impl Zone {
+    #[must_use]
+    /// Local date and time of `time` at `p`
+    pub fn local(self, time: DateTime<Utc>, p: &Point) -> NaiveDateTime {
+        match self {
+            Self::Named(tz) => time.with_timezone(&tz).naive_local(),
+            Self::Auto => {
+                #[allow(clippy::cast_possible_truncation)]
+                let hours = (wrap_lng(p.lng) / 15.0).round() as i64;
+                time.naive_utc() + Duration::hours(hours)
+            }
+        }
+    }
+}
+
+/// Range of times of day, which wraps past midnight if it ends before it starts
+#[derive(Clone, Copy)]
+pub struct TimeWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl TimeWindow {
+    #[must_use]
+    /// Whether `time` is in the window, including its start but not its end
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+}
+
+impl FromStr for TimeWindow {
+    type Err = String;
+
+    /// Parses a window in the form of start-end, with times as hours or hours:minutes (ex: 6:30-9 or 22-04:00)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || {
+            format!("time of day must be in form of start-end (ex: 6:30-9 or 22-04:00), got {s}")
+        };
+        let (start, end) = s.split_once('-').ok_or_else(error)?;
+        let parse = |time: &str| {
+            let time = time.trim();
+            NaiveTime::parse_from_str(time, "%H:%M")
+                .ok()
+                .or_else(|| {
+                    time.parse()
+                        .ok()
+                        .and_then(|hour| NaiveTime::from_hms_opt(hour, 0, 0))
+                })
+                .ok_or_else(error)
+        };
+        Ok(Self {
+            start: parse(start)?,
+            end: parse(end)?,
+        })
+    }
+}
+
+/// Set of days of the week
+#[derive(Clone, Copy)]
+pub struct Weekdays(u8);
+
+impl Weekdays {
+    #[must_use]
+    /// Whether the set contains `day`
+    pub fn contains(self, day: Weekday) -> bool {
+        self.0 & (1 << day.num_days_from_monday()) != 0
+    }
+}
+
+impl FromStr for Weekdays {
+    type Err = String;
+
+    /// Parses comma separated days (ex: mon or monday), ranges of days (ex: sat-sun), weekdays, or weekends
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let day = |day: &str| {
+            day.trim()
+                .parse::<Weekday>()
+                .map(|day| day.num_days_from_monday())
+                .map_err(|_| format!("unknown day of the week {day}"))
+        };
+        let mut days = 0;
+        for item in s.split(',') {
+            days |= match item.trim().to_lowercase().as_str() {
+                "weekdays" => 0b001_1111,
+                "weekends" => 0b110_0000,
+                item => match item.split_once('-') {
+                    Some((first, last)) => range_bits(day(first)?, day(last)?, 7),
+                    None => 1 << day(item)?,
+                },
+            };
+        }
+        Ok(Self(u8::try_from(days).expect("7 days fit in u8")))
+    }
+}
+
+/// Set of months of the year
+#[derive(Clone, Copy)]
+pub struct Months(u16);
+
+impl Months {
+    #[must_use]
+    /// Whether the set contains `month` (1 for January through 12 for December)
+    pub fn contains(self, month: u32) -> bool {
+        (1..=12).contains(&month) && self.0 & (1 << (month - 1)) != 0
+    }
+}
+
+impl FromStr for Months {
+    type Err = String;
+
+    /// Parses comma separated months (ex: 6, jun, or june), ranges of months (ex: nov-feb), or (northern hemisphere meteorological) seasons
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let month = |month: &str| {
+            let month = month.trim();
+            month
+                .parse::<u32>()
+                .ok()
+                .filter(|month| (1..=12).contains(month))
+                .or_else(|| {
+                    month
+                        .parse::<Month>()
+                        .ok()
+                        .map(|month| month.number_from_month())
+                })
+                .map(|month| month - 1)
+                .ok_or_else(|| format!("unknown month {month}"))
+        };
+        let mut months = 0;
+        for item in s.split(',') {
+            months |= match item.trim().to_lowercase().as_str() {
+                "winter" => range_bits(11, 1, 12),
+                "spring" => range_bits(2, 4, 12),
+                "summer" => range_bits(5, 7, 12),
+                "autumn" | "fall" => range_bits(8, 10, 12),
+                item => match item.split_once('-') {
+                    Some((first, last)) => range_bits(month(first)?, month(last)?, 12),
+                    None => 1 << month(item)?,
+                },
+            };
+        }
+        Ok(Self(u16::try_from(months).expect("12 months fit in u16")))
+    }
+}
+
+/// Bits `first` through `last` (wrapping around after `count` bits)
+fn range_bits(first: u32, last: u32, count: u32) -> u32 {
+    let mut bits = 0;
+    let mut i = first;
+    loop {
+        bits |= 1 << i;
+        if i == last {
+            return bits;
+        }
+        i = (i + 1) % count;
+    }
+}
+
+/// When tracks must have started, in local time, to be loaded
+#[derive(Clone, Copy, Default)]
+pub struct Schedule {
+    pub zone: Zone,
+    pub time_of_day: Option<TimeWindow>,
+    pub weekdays: Option<Weekdays>,
+    pub months: Option<Months>,
+}
+
+impl Schedule {
+    #[must_use]
+    /// Whether every track is allowed
+    pub fn is_empty(&self) -> bool {
+        self.time_of_day.is_none() && self.weekdays.is_none() && self.months.is_none()
+    }
+
+    #[must_use]
+    /// Whether the track made of `pts` started within the schedule, which is never true for tracks without timestamps unless the schedule is empty
+    pub fn allows(&self, pts: &[TrkPt]) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        let Some(start) = pts.iter().find(|pt| pt.time.is_some()) else {
+            return false;
+        };
+        let local = self.zone.local(
+            start.time.expect("point was found by its time"),
+            &start.center,
+        );
+        self.time_of_day
+            .is_none_or(|window| window.contains(local.time()))
+            && self
+                .weekdays
+                .is_none_or(|days| days.contains(local.weekday()))
+            && self
+                .months
+                .is_none_or(|months| months.contains(local.month()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schedule_allows() {
+        // Monday 2024-01-01 at 13:30 UTC, which is 07:30 in Austin
+        let pts = vec![TrkPt {
+            center: Point {
+                lat: 30.25,
+                lng: -97.75,
+            },
+            ele: None,
+            time: Some("2024-01-01T13:30:00Z".parse().unwrap()),
+        }];
+        let commute = Schedule {
+            zone: "America/Chicago".parse().unwrap(),
+            time_of_day: Some("6:30-9".parse().unwrap()),
+            weekdays: Some("weekdays".parse().unwrap()),
+            months: Some("winter".parse().unwrap()),
+        };
+        assert!(commute.allows(&pts));
+        // 13:30 is outside the window in UTC, but inside it with the offset inferred from longitude
+        let utc = Schedule {
+            zone: "UTC".parse().unwrap(),
+            ..commute
+        };
+        assert!(!utc.allows(&pts));
+        let auto = Schedule {
+            zone: Zone::Auto,
+            ..commute
+        };
+        assert!(auto.allows(&pts));
+        let weekends = Schedule {
+            weekdays: Some("sat-sun".parse().unwrap()),
+            ..commute
+        };
+        assert!(!weekends.allows(&pts));
+        assert!("jun-aug,13".parse::<Months>().is_err());
+    }
+}
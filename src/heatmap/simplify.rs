@@ -0,0 +1,286 @@
+use super::{haversine, offset_meters, segment_distance, wrap_lng, GapRule, Point, TrkPt};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::str::FromStr;
+
+/// Algorithm used to drop points that barely change the shape of a track
+#[derive(Clone, Copy)]
+pub enum Method {
+    /// keeps points further than the tolerance from the line between kept points
+    DouglasPeucker,
+    /// drops the points forming the smallest triangles with their neighbors, until every triangle has an area of at least the tolerance squared
+    Visvalingam,
+}
+
+impl FromStr for Method {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dp" | "douglas-peucker" => Ok(Self::DouglasPeucker),
+            "vw" | "visvalingam" => Ok(Self::Visvalingam),
+            _ => Err(format!(
+                "simplify method must be dp (douglas-peucker) or vw (visvalingam), got {s}"
+            )),
+        }
+    }
+}
+
+/// How tracks are thinned out after parsing
+pub struct Simplify {
+    /// simplification algorithm and its tolerance in meters
+    pub method: Option<(Method, f64)>,
+    /// meters between points after resampling
+    pub resample: Option<f64>,
+    /// lines broken by this are never joined, and lines it doesn't break stay unbroken
+    pub gap: GapRule,
+}
+
+impl Simplify {
+    #[must_use]
+    /// Simplifies and then resamples `pts`
+    pub fn apply(&self, pts: Vec<TrkPt>) -> Vec<TrkPt> {
+        let pts = match self.method {
+            Some((method, tolerance)) => simplify(pts, method, tolerance, &self.gap),
+            None => pts,
+        };
+        match self.resample {
+            Some(spacing) => resample(&pts, spacing, &self.gap),
+            None => pts,
+        }
+    }
+}
+
+/// Ranges of consecutive points of `pts` connected by `gap`
+fn runs(pts: &[TrkPt], gap: &GapRule) -> Vec<(usize, usize)> {
+    let mut runs = Vec::new();
+    let mut first = 0;
+    for i in 1..=pts.len() {
+        if i == pts.len() || !gap.connects(&pts[i - 1], &pts[i]) {
+            runs.push((first, i));
+            first = i;
+        }
+    }
+    runs
+}
+
+/// Drops points of `pts` using `method` within each run of connected points, keeping extra points where needed so that no new gaps are created
+fn simplify(pts: Vec<TrkPt>, method: Method, tolerance: f64, gap: &GapRule) -> Vec<TrkPt> {
+    let mut keep = vec![false; pts.len()];
+    for (first, last) in runs(&pts, gap) {
+        let run = &pts[first..last];
+        let kept = &mut keep[first..last];
+        match method {
+            Method::DouglasPeucker => douglas_peucker(run, tolerance, kept),
+            Method::Visvalingam => visvalingam(run, tolerance * tolerance, kept),
+        }
+
+        // keep a dropped point if the line from the last kept point to the point after it would be broken
+        let mut prev = 0;
+        for i in 1..run.len() {
+            if !kept[i] && i + 1 < run.len() && !gap.connects(&run[prev], &run[i + 1]) {
+                kept[i] = true;
+            }
+            if kept[i] {
+                prev = i;
+            }
+        }
+    }
+
+    pts.into_iter()
+        .zip(keep)
+        .filter(|&(_, keep)| keep)
+        .map(|(pt, _)| pt)
+        .collect()
+}
+
+/// Marks the points of `run` to keep with Douglas-Peucker simplification
+fn douglas_peucker(run: &[TrkPt], tolerance: f64, keep: &mut [bool]) {
+    let Some(last) = run.len().checked_sub(1) else {
+        return;
+    };
+    keep[0] = true;
+    keep[last] = true;
+
+    let mut stack = vec![(0, last)];
+    while let Some((first, last)) = stack.pop() {
+        let furthest = (first + 1..last)
+            .map(|i| {
+                let distance =
+                    segment_distance(&run[i].center, &run[first].center, &run[last].center);
+                (i, distance)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((i, distance)) = furthest {
+            if distance > tolerance {
+                keep[i] = true;
+                stack.push((first, i));
+                stack.push((i, last));
+            }
+        }
+    }
+}
+
+/// Marks the points of `run` to keep with Visvalingam-Whyatt simplification, dropping points with effective areas below `min_area` square meters
+fn visvalingam(run: &[TrkPt], min_area: f64, keep: &mut [bool]) {
+    let len = run.len();
+    keep.fill(true);
+    if len < 3 {
+        return;
+    }
+
+    // neighbors of each point that haven't been dropped
+    let mut prev: Vec<usize> = (0..len).map(|i| i.saturating_sub(1)).collect();
+    let mut next: Vec<usize> = (1..=len).collect();
+    let area = |a: usize, b: usize, c: usize| {
+        let (ax, ay) = offset_meters(&run[b].center, &run[a].center);
+        let (cx, cy) = offset_meters(&run[b].center, &run[c].center);
+        ax.mul_add(cy, -(ay * cx)).abs() / 2.0
+    };
+    let mut areas: Vec<f64> = (0..len)
+        .map(|i| {
+            if i == 0 || i == len - 1 {
+                f64::INFINITY
+            } else {
+                area(i - 1, i, i + 1)
+            }
+        })
+        .collect();
+
+    // smallest areas first, with non-negative areas ordered by their bits
+    let mut heap: BinaryHeap<Reverse<(u64, usize)>> = (1..len - 1)
+        .map(|i| Reverse((areas[i].to_bits(), i)))
+        .collect();
+    while let Some(Reverse((bits, i))) = heap.pop() {
+        if !keep[i] || bits != areas[i].to_bits() {
+            // dropped or area changed since it was pushed
+            continue;
+        }
+        if areas[i] >= min_area {
+            break;
+        }
+        keep[i] = false;
+        let (p, n) = (prev[i], next[i]);
+        next[p] = n;
+        prev[n] = p;
+        for j in [p, n] {
+            if j != 0 && j != len - 1 {
+                areas[j] = area(prev[j], j, next[j]);
+                heap.push(Reverse((areas[j].to_bits(), j)));
+            }
+        }
+    }
+}
+
+/// Replaces `pts` with points every `spacing` meters along it (keeping its ends), interpolating positions and timestamps
+/// Points of `pts` are kept where needed so that lines broken by `gap` stay broken and no new gaps are created
+fn resample(pts: &[TrkPt], spacing: f64, gap: &GapRule) -> Vec<TrkPt> {
+    let mut resampled = Vec::new();
+    for (first, last) in runs(pts, gap) {
+        let run = &pts[first..last];
+        resampled.push(run[0].clone());
+        // meters along the run since the last resampled point
+        let mut since = 0.0;
+        for i in 1..run.len() {
+            let (a, b) = (&run[i - 1], &run[i]);
+            let length = haversine(&a.center, &b.center);
+            let mut along = 0.0;
+            while since + (length - along) >= spacing {
+                along += spacing - since;
+                resampled.push(interpolate(a, b, along / length));
+                since = 0.0;
+            }
+            since += length - along;
+
+            let last_kept = resampled.last().expect("run starts with a point");
+            let is_end = i == run.len() - 1;
+            if since > 0.0 && (is_end || !gap.connects(last_kept, &run[i + 1])) {
+                resampled.push(b.clone());
+                since = 0.0;
+            }
+        }
+    }
+    resampled
+}
+
+/// Point `t` of the way from `a` to `b`
+fn interpolate(a: &TrkPt, b: &TrkPt, t: f64) -> TrkPt {
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_precision_loss)]
+    let time = a.time.zip(b.time).map(|(a_time, b_time)| {
+        let millis = (b_time - a_time).num_milliseconds() as f64 * t;
+        a_time + chrono::Duration::milliseconds(millis.round() as i64)
+    });
+    TrkPt {
+        center: Point {
+            lat: t.mul_add(b.center.lat - a.center.lat, a.center.lat),
+            lng: wrap_lng(t.mul_add(wrap_lng(b.center.lng - a.center.lng), a.center.lng)),
+        },
+        ele: a
+            .ele
+            .zip(b.ele)
+            .map(|(a_ele, b_ele)| t.mul_add(b_ele - a_ele, a_ele)),
+        time,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Duration, Utc};
+
+    /// ~11m north every second, with a ~50m detour east in the middle
+    fn track() -> Vec<TrkPt> {
+        let start: DateTime<Utc> = "2024-01-01T00:00:00Z".parse().unwrap();
+        (0..101)
+            .map(|i| TrkPt {
+                center: Point {
+                    lat: 30.0 + f64::from(i) * 0.0001,
+                    lng: if i == 50 { -96.9995 } else { -97.0 },
+                },
+                ele: None,
+                time: Some(start + Duration::seconds(i64::from(i))),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn simplify_and_resample() {
+        let gap = GapRule {
+            seconds: f64::INFINITY,
+            meters: f64::INFINITY,
+        };
+        for method in [Method::DouglasPeucker, Method::Visvalingam] {
+            let simplified = Simplify {
+                method: Some((method, 10.0)),
+                resample: None,
+                gap,
+            }
+            .apply(track());
+            // both ends, the detour, and the points on either side of it
+            assert_eq!(simplified.len(), 5);
+            assert!(simplified.iter().any(|pt| pt.center.lng > -97.0));
+        }
+
+        // the default 5 second gap keeps at least every 5th point
+        let simplified = Simplify {
+            method: Some((Method::DouglasPeucker, 10.0)),
+            resample: None,
+            gap: GapRule::default(),
+        }
+        .apply(track());
+        assert!(simplified
+            .windows(2)
+            .all(|pair| GapRule::default().connects(&pair[0], &pair[1])));
+
+        let resampled = Simplify {
+            method: None,
+            resample: Some(100.0),
+            gap,
+        }
+        .apply(track());
+        // ~1112m of track plus a ~100m detour, resampled every 100m with the end kept
+        assert_eq!(resampled.len(), 13);
+        assert!((haversine(&resampled[0].center, &resampled[1].center) - 100.0).abs() < 0.5);
+    }
+}
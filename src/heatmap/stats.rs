@@ -0,0 +1,321 @@
+use super::metrics::Metrics;
+use super::schedule::Zone;
+use super::TrkPt;
+use chrono::NaiveDate;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::str::FromStr;
+
+#[derive(Clone, Copy)]
+pub enum Format {
+    Table,
+    Csv,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "table" => Ok(Self::Table),
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            _ => Err(format!(
+                "unknown stats format {s}, expected table, csv, or json"
+            )),
+        }
+    }
+}
+
+/// Periods that tracks are totaled over
+#[derive(Clone, Copy)]
+pub enum GroupBy {
+    Year,
+    Month,
+    /// every track together, regardless of date
+    All,
+}
+
+impl FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "year" => Ok(Self::Year),
+            "month" => Ok(Self::Month),
+            "all" => Ok(Self::All),
+            _ => Err(format!(
+                "unknown stats grouping {s}, expected year, month, or all"
+            )),
+        }
+    }
+}
+
+/// Totals of a group of tracks
+#[derive(Default)]
+pub struct Totals {
+    pub activities: usize,
+    /// meters
+    pub distance: f64,
+    /// seconds spent moving, counting only tracks with timestamps
+    pub moving: f64,
+    /// meters climbed, counting only tracks with elevations
+    pub elevation_gain: f64,
+    /// meters of the longest track
+    pub longest: f64,
+    /// local date of the earliest track start, if any track has timestamps
+    pub first: Option<NaiveDate>,
+    /// local date of the latest track start, if any track has timestamps
+    pub last: Option<NaiveDate>,
+}
+
+impl Totals {
+    /// Adds the track made of `pts`, which started on local date `date` (if it has timestamps)
+    fn add(&mut self, pts: &[TrkPt], date: Option<NaiveDate>) {
+        let metrics = Metrics::new(pts);
+        self.activities += 1;
+        self.distance += metrics.distance;
+        self.moving += metrics.moving.unwrap_or(0.0);
+        self.elevation_gain += metrics.elevation_gain.unwrap_or(0.0);
+        self.longest = self.longest.max(metrics.distance);
+        if let Some(date) = date {
+            self.first = Some(self.first.map_or(date, |first| first.min(date)));
+            self.last = Some(self.last.map_or(date, |last| last.max(date)));
+        }
+    }
+}
+
+/// Totals of the tracks of one activity type (or all types) in one period
+pub struct Row {
+    pub activity: String,
+    /// year (ex: 2021), month (ex: 2021-06), "all", or "unknown" for tracks without timestamps
+    pub period: String,
+    pub totals: Totals,
+}
+
+#[must_use]
+/// Totals `trk_pts` by the period each track started in (in local time from `zone`), labeling every row with `activity`
+/// Rows are in order of period, with tracks without timestamps last
+pub fn rows<'a>(
+    activity: &str,
+    trk_pts: impl IntoIterator<Item = &'a Vec<TrkPt>>,
+    by: GroupBy,
+    zone: Zone,
+) -> Vec<Row> {
+    let mut periods: BTreeMap<(bool, String), Totals> = BTreeMap::new();
+    for pts in trk_pts {
+        let date = pts
+            .iter()
+            .find_map(|pt| pt.time.map(|time| zone.local(time, &pt.center).date()));
+        let period = match (by, date) {
+            (GroupBy::All, _) => "all".to_string(),
+            (GroupBy::Year, Some(date)) => date.format("%Y").to_string(),
+            (GroupBy::Month, Some(date)) => date.format("%Y-%m").to_string(),
+            (_, None) => "unknown".to_string(),
+        };
+        periods
+            .entry((date.is_none() && !matches!(by, GroupBy::All), period))
+            .or_default()
+            .add(pts, date);
+    }
+
+    periods
+        .into_iter()
+        .map(|((_, period), totals)| Row {
+            activity: activity.to_string(),
+            period,
+            totals,
+        })
+        .collect()
+}
+
+#[must_use]
+/// Formats `rows` as an aligned table (in km and hours), CSV, or a JSON array (both in meters and seconds)
+pub fn format(rows: &[Row], format: Format) -> String {
+    match format {
+        Format::Table => table(rows),
+        Format::Csv => {
+            let mut csv = String::from(
+                "activity,period,activities,distance_m,moving_s,elevation_gain_m,longest_m,first,last\n",
+            );
+            for row in rows {
+                let totals = &row.totals;
+                writeln!(
+                    csv,
+                    "{},{},{},{:.0},{:.0},{:.0},{:.0},{},{}",
+                    row.activity,
+                    row.period,
+                    totals.activities,
+                    totals.distance,
+                    totals.moving,
+                    totals.elevation_gain,
+                    totals.longest,
+                    date(totals.first),
+                    date(totals.last)
+                )
+                .expect("writing to a string can't fail");
+            }
+            csv
+        }
+        Format::Json => {
+            let rows: Vec<_> = rows
+                .iter()
+                .map(|row| {
+                    let totals = &row.totals;
+                    json!({
+                        "activity": row.activity,
+                        "period": row.period,
+                        "activities": totals.activities,
+                        "distance_m": totals.distance.round(),
+                        "moving_s": totals.moving.round(),
+                        "elevation_gain_m": totals.elevation_gain.round(),
+                        "longest_m": totals.longest.round(),
+                        "first": totals.first.map(|date| date.to_string()),
+                        "last": totals.last.map(|date| date.to_string()),
+                    })
+                })
+                .collect();
+            let mut json = serde_json::to_string_pretty(&rows).expect("stats must serialize");
+            json.push('\n');
+            json
+        }
+    }
+}
+
+/// Formats `rows` as a table with a header and a line per row, with text left aligned and numbers right aligned
+fn table(rows: &[Row]) -> String {
+    let cells: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| {
+            let totals = &row.totals;
+            vec![
+                row.activity.clone(),
+                row.period.clone(),
+                totals.activities.to_string(),
+                format!("{:.1}", totals.distance / 1000.0),
+                format!("{:.1}", totals.moving / 3600.0),
+                format!("{:.0}", totals.elevation_gain),
+                format!("{:.1}", totals.longest / 1000.0),
+                date(totals.first),
+                date(totals.last),
+            ]
+        })
+        .collect();
+    align(
+        &[
+            "Activity",
+            "Period",
+            "Activities",
+            "Distance (km)",
+            "Moving (h)",
+            "Climbed (m)",
+            "Longest (km)",
+            "First",
+            "Last",
+        ],
+        &cells,
+        &[2, 3, 4, 5, 6],
+    )
+}
+
+#[must_use]
+/// Lines up `cells` in columns under `header`, with the columns in `numbers` right aligned and the rest left aligned
+pub fn align(header: &[&str], cells: &[Vec<String>], numbers: &[usize]) -> String {
+    let widths: Vec<usize> = (0..header.len())
+        .map(|i| {
+            cells
+                .iter()
+                .map(|row| row[i].chars().count())
+                .chain([header[i].len()])
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+
+    let mut table = String::new();
+    let header: Vec<String> = header.iter().map(ToString::to_string).collect();
+    for row in std::iter::once(&header).chain(cells) {
+        let mut line = String::new();
+        for (i, (cell, width)) in row.iter().zip(&widths).enumerate() {
+            if numbers.contains(&i) {
+                write!(line, "{cell:>width$}  ").expect("writing to a string can't fail");
+            } else {
+                write!(line, "{cell:<width$}  ").expect("writing to a string can't fail");
+            }
+        }
+        table.push_str(line.trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+/// `date` as YYYY-MM-DD, or empty if unknown
+fn date(date: Option<NaiveDate>) -> String {
+    date.map_or_else(String::new, |date| date.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heatmap::Point;
+    use chrono::{DateTime, Duration, Utc};
+
+    /// A ~1.1km track north starting at `start`, climbing 1m every 10 seconds
+    fn track(start: &str) -> Vec<TrkPt> {
+        let start: DateTime<Utc> = start.parse().unwrap();
+        (0..=10)
+            .map(|i| TrkPt {
+                center: Point {
+                    lat: 30.0 + f64::from(i) * 0.001,
+                    lng: -97.0,
+                },
+                ele: Some(100.0 + f64::from(i)),
+                time: Some(start + Duration::seconds(i64::from(i) * 10)),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stats_rows() {
+        let mut trk_pts = vec![
+            track("2021-06-01T12:00:00Z"),
+            track("2020-12-31T12:00:00Z"),
+            track("2021-01-01T03:00:00Z"),
+        ];
+        trk_pts[2].truncate(6);
+        let mut untimed = track("2021-06-01T12:00:00Z");
+        for pt in &mut untimed {
+            pt.time = None;
+        }
+        trk_pts.push(untimed);
+
+        // the last track starts on New Year's Eve in Austin
+        let rows = rows("Bike", &trk_pts, GroupBy::Year, Zone::Auto);
+        let periods: Vec<&str> = rows.iter().map(|row| row.period.as_str()).collect();
+        assert_eq!(periods, ["2020", "2021", "unknown"]);
+        let totals = &rows[0].totals;
+        assert_eq!(totals.activities, 2);
+        assert!((totals.distance - 1667.9).abs() < 1.0);
+        assert!((totals.moving - 150.0).abs() < 0.001);
+        // climbs short of the threshold at the tops of the tracks aren't counted
+        assert!((totals.elevation_gain - 12.0).abs() < 0.001);
+        assert!((totals.longest - 1111.9).abs() < 1.0);
+        assert_eq!(totals.first, NaiveDate::from_ymd_opt(2020, 12, 31));
+        assert_eq!(rows[2].totals.first, None);
+
+        let all = super::rows("All", &trk_pts, GroupBy::All, Zone::Auto);
+        assert_eq!(all.len(), 1);
+        assert_eq!(all[0].totals.activities, 4);
+
+        let csv = format(&rows, Format::Csv);
+        assert_eq!(
+            csv.lines().nth(1),
+            Some("Bike,2020,2,1668,150,12,1112,2020-12-31,2020-12-31")
+        );
+        let json: serde_json::Value = serde_json::from_str(&format(&rows, Format::Json)).unwrap();
+        assert_eq!(json[1]["period"], "2021");
+        assert_eq!(format(&rows, Format::Table).lines().count(), 4);
+    }
+}
@@ -1,28 +1,21 @@
+use super::parse_text;
 use chrono::{DateTime, Utc};
 use quick_xml::events::{BytesStart, Event};
 use quick_xml::Reader;
+use regex::Regex;
 use simple_error::bail;
 use std::error::Error;
 
 pub fn get_pts(
     mut reader: Reader<&[u8]>,
-    type_filters: &Option<Vec<super::ActivityType>>,
-    start: &Option<DateTime<Utc>>,
-    end: &Option<DateTime<Utc>>,
-) -> Result<Vec<super::TrkPt>, Box<dyn Error>> {
+    type_filter: Option<&super::activity::TypeFilter>,
+    exclude_name: Option<&Regex>,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+) -> Result<super::Track, Box<dyn Error>> {
     let mut buf = Vec::new();
 
-    let filter_strings = type_filters.as_ref().map(|fs| {
-        fs.iter()
-            .map(|f| match f {
-                super::ActivityType::Bike => "Biking",
-                super::ActivityType::Run => "Running",
-                super::ActivityType::Walk => "Other",
-            })
-            .collect()
-    });
-
-    let mut trk_pts = None;
+    let mut track = super::Track::default();
 
     loop {
         buf.clear();
@@ -30,13 +23,7 @@ pub fn get_pts(
         match reader.read_event(&mut buf) {
             Ok(Event::Start(ref e)) => {
                 if let b"Activity" = e.name() {
-                    trk_pts = Some(parse_activity(
-                        &mut reader,
-                        e,
-                        filter_strings.as_ref(),
-                        start,
-                        end,
-                    )?);
+                    track = parse_activity(&mut reader, e, type_filter, exclude_name, start, end)?;
                 }
             }
             Ok(Event::Eof) => break,
@@ -45,49 +32,49 @@ pub fn get_pts(
         }
     }
 
-    match trk_pts {
-        Some(t) => Ok(t),
-        None => Ok(Vec::new()),
-    }
+    Ok(track)
 }
 
 fn parse_activity(
     reader: &mut Reader<&[u8]>,
     event: &BytesStart,
-    filter_strings: Option<&Vec<&str>>,
-    start: &Option<DateTime<Utc>>,
-    end: &Option<DateTime<Utc>>,
-) -> Result<Vec<super::TrkPt>, Box<dyn Error>> {
+    type_filter: Option<&super::activity::TypeFilter>,
+    exclude_name: Option<&Regex>,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+) -> Result<super::Track, Box<dyn Error>> {
     let mut buf = Vec::new();
 
-    let mut trk_pts = None;
+    let mut track = super::Track::default();
 
-    // Check if activity type matches provided filter
-    if let Some(filter_strings) = filter_strings {
-        for attr in event.attributes().flatten() {
-            if let b"Sport" = attr.key {
-                let sport = &attr.unescaped_value()?;
-                let sport = std::str::from_utf8(sport)?;
-                if !filter_strings.contains(&sport) {
-                    return Ok(Vec::new());
-                }
+    for attr in event.attributes().flatten() {
+        if let b"Sport" = attr.key {
+            let sport = &attr.unescaped_value()?;
+            let sport = std::str::from_utf8(sport)?;
+            // Check if activity type matches provided filter
+            if type_filter.is_some_and(|type_filter| !type_filter.allows(sport)) {
+                return Ok(super::Track::default());
             }
+            track.activity = Some(sport.to_string());
         }
     }
 
     loop {
         match reader.read_event(&mut buf) {
-            Ok(Event::Start(ref e)) => {
-                if let b"Lap" = e.name() {
-                    trk_pts = Some(parse_lap(reader, e, start, end)?);
+            Ok(Event::Start(ref e)) => match e.name() {
+                b"Lap" => parse_lap(reader, e, start, end, &mut track)?,
+                b"Notes" => {
+                    let notes = parse_text(reader, &mut buf, b"Notes")?;
+                    if exclude_name.is_some_and(|exclude_name| exclude_name.is_match(&notes)) {
+                        return Ok(super::Track::default());
+                    }
+                    track.name = Some(notes);
                 }
-            }
+                _ => (),
+            },
             Ok(Event::End(ref e)) => {
                 if let b"Activity" = e.name() {
-                    match trk_pts {
-                        Some(t) => return Ok(t),
-                        None => return Ok(Vec::new()),
-                    }
+                    return Ok(track);
                 }
             }
             Ok(Event::Eof) => bail!("Hit EOF while in <Activity>"),
@@ -99,50 +86,50 @@ fn parse_activity(
     }
 }
 
+/// Reads the points of the lap into `track`, replacing any from earlier laps, or no points if the lap's start time isn't between `start` and `end`
 fn parse_lap(
     reader: &mut Reader<&[u8]>,
     event: &BytesStart,
-    start: &Option<DateTime<Utc>>,
-    end: &Option<DateTime<Utc>>,
-) -> Result<Vec<super::TrkPt>, Box<dyn Error>> {
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+    track: &mut super::Track,
+) -> Result<(), Box<dyn Error>> {
     let mut buf = Vec::new();
 
-    let mut trk_pts = None;
+    track.pts = Vec::new();
+    track.time = None;
+    track.invalid_time = false;
 
-    // check file time if start or end filters are set
-    if start.is_some() || end.is_some() {
-        for attr in event.attributes().flatten() {
-            if let b"StartTime" = attr.key {
-                let time =
-                    std::str::from_utf8(&attr.unescaped_value()?)?.parse::<DateTime<Utc>>()?;
-                // return no points if start time is before start or after end filters
-                if let Some(start) = start {
-                    if time < *start {
-                        return Ok(Vec::new());
-                    }
-                }
-                if let Some(end) = end {
-                    if time > *end {
-                        return Ok(Vec::new());
-                    }
+    // check lap time against the start or end filters
+    for attr in event.attributes().flatten() {
+        if let b"StartTime" = attr.key {
+            let text = std::str::from_utf8(&attr.unescaped_value()?)?.to_string();
+            match text.parse::<DateTime<Utc>>() {
+                Ok(time) => track.time = Some(time),
+                Err(e) => {
+                    track
+                        .warnings
+                        .push(format!("Invalid lap StartTime {text}: {e}"));
+                    track.invalid_time = true;
                 }
             }
         }
     }
+    // no points if start time is before start or at or after end filters (which is exclusive)
+    if !super::in_period(track.time, track.invalid_time, start, end) {
+        return Ok(());
+    }
 
     loop {
         match reader.read_event(&mut buf) {
             Ok(Event::Start(ref e)) => {
                 if let b"Track" = e.name() {
-                    trk_pts = Some(parse_track(reader, &mut buf)?);
+                    track.pts = parse_track(reader, &mut buf, &mut track.warnings)?;
                 }
             }
             Ok(Event::End(ref e)) => {
                 if let b"Lap" = e.name() {
-                    match trk_pts {
-                        Some(t) => return Ok(t),
-                        None => return Ok(Vec::new()),
-                    }
+                    return Ok(());
                 }
             }
             Ok(Event::Eof) => bail!("Hit EOF while in <Lap>"),
@@ -154,9 +141,11 @@ fn parse_lap(
     }
 }
 
+/// Parses the points of a track, adding the problems with any that are skipped to `warnings`
 fn parse_track(
     reader: &mut Reader<&[u8]>,
     buf: &mut Vec<u8>,
+    warnings: &mut Vec<String>,
 ) -> Result<Vec<super::TrkPt>, Box<dyn Error>> {
     let mut trk_pts = Vec::new();
 
@@ -166,9 +155,9 @@ fn parse_track(
         match reader.read_event(buf) {
             Ok(Event::Start(ref e)) => {
                 if let b"Trackpoint" = e.name() {
-                    match parse_trackpoint(reader, buf) {
+                    match parse_trackpoint(reader, buf, warnings) {
                         Ok(pt) => trk_pts.push(pt),
-                        Err(e) => eprintln!("{e}"),
+                        Err(e) => warnings.push(e.to_string()),
                     }
                 }
             }
@@ -187,8 +176,10 @@ fn parse_track(
 fn parse_trackpoint(
     reader: &mut Reader<&[u8]>,
     buf: &mut Vec<u8>,
+    warnings: &mut Vec<String>,
 ) -> Result<super::TrkPt, Box<dyn Error>> {
     let mut point = None;
+    let mut ele = None;
     let mut time = None;
 
     loop {
@@ -199,16 +190,22 @@ fn parse_trackpoint(
                 b"Position" => {
                     point = Some(parse_position(reader, buf)?);
                 }
+                b"AltitudeMeters" => {
+                    ele = parse_text(reader, buf, b"AltitudeMeters")?
+                        .trim()
+                        .parse()
+                        .ok();
+                }
                 b"Time" => match parse_time(reader, buf) {
                     Ok(t) => time = Some(t),
-                    Err(e) => eprintln!("{e}"),
+                    Err(e) => warnings.push(e.to_string()),
                 },
                 _ => (),
             },
             Ok(Event::End(ref e)) => {
                 if let b"Trackpoint" = e.name() {
                     match point {
-                        Some(center) => return Ok(super::TrkPt { center, time }),
+                        Some(center) => return Ok(super::TrkPt { center, ele, time }),
                         None => bail!("Incomplete <Trackpoint>: {:?} {:?} ", point, time),
                     }
                 }
@@ -0,0 +1,293 @@
+use super::annotate::fill_rect;
+use super::{GapRule, MapInfo, Point, TrkPt};
+use image::{Rgb, RgbImage};
+use std::collections::{HashSet, VecDeque};
+use std::f64::consts::PI;
+
+const TILE_ALPHA: f64 = 0.2;
+const CLUSTER_ALPHA: f64 = 0.4;
+const SQUARE_COLOR: Rgb<u8> = Rgb([255, 255, 255]);
+const SQUARE_BORDER: u32 = 4;
+
+/// Slippy map tile coordinates
+pub type Tile = (u32, u32);
+
+/// Explorer tiles visited by a set of tracks at a single zoom level
+pub struct Explorer {
+    pub zoom: u8,
+    pub tiles: HashSet<Tile>,
+    /// Largest connected group of visited tiles whose 4 neighbors are all visited
+    pub cluster: HashSet<Tile>,
+    /// Top left tile and side length (in tiles) of the largest fully visited square
+    pub square: (Tile, u32),
+}
+
+#[must_use]
+/// Finds all tiles at `zoom` touched by `trk_pts`, including tiles crossed by the line between consecutive points not separated by `gap`
+pub fn explore(trk_pts: &[Vec<TrkPt>], zoom: u8, gap: &GapRule) -> Explorer {
+    let mut tiles = HashSet::new();
+
+    for v in trk_pts {
+        let mut prev: Option<&TrkPt> = None;
+        for pt in v {
+            let to = tile_position(&pt.center, zoom);
+            match prev {
+                Some(prev) if gap.connects(prev, pt) => {
+                    let from = tile_position(&prev.center, zoom);
+                    // cross the antimeridian instead of the rest of the world
+                    let n = f64::from(1_u32 << zoom);
+                    let to = (to.0 + ((from.0 - to.0) / n).round() * n, to.1);
+                    line_tiles(from, to, zoom, &mut tiles);
+                }
+                _ => {
+                    tiles.insert(clamp_tile(to.0.floor(), to.1.floor(), zoom));
+                }
+            }
+            prev = Some(pt);
+        }
+    }
+
+    let cluster = largest_cluster(&tiles);
+    let square = max_square(&tiles);
+
+    Explorer {
+        zoom,
+        tiles,
+        cluster,
+        square,
+    }
+}
+
+/// Shades every tile in `explorer` onto `map_image` in `color`, with tiles in the largest cluster shaded more heavily and the max square outlined
+pub fn draw_tiles(
+    map_image: &mut RgbImage,
+    map_info: &MapInfo,
+    explorer: &Explorer,
+    color: Rgb<u8>,
+) {
+    for tile in &explorer.tiles {
+        let alpha = if explorer.cluster.contains(tile) {
+            CLUSTER_ALPHA
+        } else {
+            TILE_ALPHA
+        };
+        if let Some((x, y, width, height)) = tile_rect(map_info, *tile, 1, explorer.zoom) {
+            // leave a 1 pixel gap on the right and bottom so the tile grid stays visible
+            fill_rect(
+                map_image,
+                x,
+                y,
+                width.saturating_sub(1),
+                height.saturating_sub(1),
+                color,
+                alpha,
+            );
+        }
+    }
+
+    let (corner, size) = explorer.square;
+    if size == 0 {
+        return;
+    }
+    if let Some((x, y, width, height)) = tile_rect(map_info, corner, size, explorer.zoom) {
+        let right = (x + width).saturating_sub(SQUARE_BORDER);
+        let bottom = (y + height).saturating_sub(SQUARE_BORDER);
+        fill_rect(map_image, x, y, width, SQUARE_BORDER, SQUARE_COLOR, 1.0);
+        fill_rect(
+            map_image,
+            x,
+            bottom,
+            width,
+            SQUARE_BORDER,
+            SQUARE_COLOR,
+            1.0,
+        );
+        fill_rect(map_image, x, y, SQUARE_BORDER, height, SQUARE_COLOR, 1.0);
+        fill_rect(
+            map_image,
+            right,
+            y,
+            SQUARE_BORDER,
+            height,
+            SQUARE_COLOR,
+            1.0,
+        );
+    }
+}
+
+#[must_use]
+/// Fractional slippy map tile coordinates of `p` at `zoom`
+pub fn tile_position(p: &Point, zoom: u8) -> (f64, f64) {
+    let n = f64::from(1_u32 << zoom);
+    let lat_rad = p.lat.to_radians();
+    let x = (p.lng + 180.0) / 360.0 * n;
+    let y = (1.0 - lat_rad.tan().asinh() / PI) / 2.0 * n;
+    (x, y)
+}
+
+#[must_use]
+/// Latitude and longitude of the northwest corner of `tile` at `zoom`
+pub fn tile_corner(tile: Tile, zoom: u8) -> Point {
+    let n = f64::from(1_u32 << zoom);
+    let lng = f64::from(tile.0) / n * 360.0 - 180.0;
+    let lat = (PI * (1.0 - 2.0 * f64::from(tile.1) / n))
+        .sinh()
+        .atan()
+        .to_degrees();
+    Point { lat, lng }
+}
+
+/// Pixel rectangle (x, y, width, height) on the map covered by the `size` x `size` block of tiles with its top left at `tile`, clipped to the top left of the map
+/// Returns `None` if the block is entirely off the top or left of the map
+fn tile_rect(map_info: &MapInfo, tile: Tile, size: u32, zoom: u8) -> Option<(u32, u32, u32, u32)> {
+    let (x1, y1) = map_info.pixel(&tile_corner(tile, zoom));
+    let (x2, y2) = map_info.pixel(&tile_corner((tile.0 + size, tile.1 + size), zoom));
+    if x2 < 0.0 || y2 < 0.0 {
+        return None;
+    }
+    let (x1, y1) = (x1.max(0.0).round(), y1.max(0.0).round());
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_sign_loss)]
+    Some((
+        x1 as u32,
+        y1 as u32,
+        (x2.round() - x1) as u32,
+        (y2.round() - y1) as u32,
+    ))
+}
+
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_sign_loss)]
+/// Converts floored fractional tile coordinates into a tile that exists at `zoom`, wrapping x around the antimeridian and clamping y
+fn clamp_tile(x: f64, y: f64, zoom: u8) -> Tile {
+    let n = f64::from(1_u32 << zoom);
+    (x.rem_euclid(n) as u32, y.clamp(0.0, n - 1.0) as u32)
+}
+
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_sign_loss)]
+/// Adds every tile crossed by the straight line between fractional tile positions `from` and `to` to `tiles`
+fn line_tiles(from: (f64, f64), to: (f64, f64), zoom: u8, tiles: &mut HashSet<Tile>) {
+    let (mut x, mut y) = (from.0.floor(), from.1.floor());
+    let (end_x, end_y) = (to.0.floor(), to.1.floor());
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+
+    // distance along the line (as a fraction of its length) between crossing tile edges, and to the first crossing
+    let (step_x, delta_x, mut next_x) = edge_crossings(from.0, dx);
+    let (step_y, delta_y, mut next_y) = edge_crossings(from.1, dy);
+
+    tiles.insert(clamp_tile(x, y, zoom));
+    // each step crosses exactly one tile edge
+    let steps = (end_x - x).abs() + (end_y - y).abs();
+    for _ in 0..steps as u64 {
+        if next_x < next_y {
+            x += step_x;
+            next_x += delta_x;
+        } else {
+            y += step_y;
+            next_y += delta_y;
+        }
+        tiles.insert(clamp_tile(x, y, zoom));
+    }
+}
+
+/// Returns the direction of travel along an axis, the fraction of the line between crossing tile edges, and the fraction to the first crossing
+fn edge_crossings(start: f64, delta: f64) -> (f64, f64, f64) {
+    if delta > 0.0 {
+        (1.0, 1.0 / delta, (start.floor() + 1.0 - start) / delta)
+    } else if delta < 0.0 {
+        (-1.0, -1.0 / delta, (start - start.floor()) / -delta)
+    } else {
+        (0.0, f64::INFINITY, f64::INFINITY)
+    }
+}
+
+/// Neighbors of `tile` sharing an edge with it
+fn neighbors(tile: Tile) -> [Tile; 4] {
+    let (x, y) = tile;
+    [
+        (x.wrapping_sub(1), y),
+        (x + 1, y),
+        (x, y.wrapping_sub(1)),
+        (x, y + 1),
+    ]
+}
+
+/// Finds the largest connected group of tiles in `tiles` that have all 4 neighbors in `tiles`
+fn largest_cluster(tiles: &HashSet<Tile>) -> HashSet<Tile> {
+    let surrounded: HashSet<Tile> = tiles
+        .iter()
+        .filter(|&&tile| neighbors(tile).iter().all(|n| tiles.contains(n)))
+        .copied()
+        .collect();
+
+    let mut largest = HashSet::new();
+    let mut seen = HashSet::new();
+    for &tile in &surrounded {
+        if !seen.insert(tile) {
+            continue;
+        }
+        // flood fill the cluster containing tile
+        let mut cluster = HashSet::from([tile]);
+        let mut queue = VecDeque::from([tile]);
+        while let Some(curr) = queue.pop_front() {
+            for n in neighbors(curr) {
+                if surrounded.contains(&n) && seen.insert(n) {
+                    cluster.insert(n);
+                    queue.push_back(n);
+                }
+            }
+        }
+        if cluster.len() > largest.len() {
+            largest = cluster;
+        }
+    }
+
+    largest
+}
+
+/// Finds the top left tile and side length of the largest square of tiles all contained in `tiles`
+fn max_square(tiles: &HashSet<Tile>) -> (Tile, u32) {
+    let mut sorted: Vec<Tile> = tiles.iter().copied().collect();
+    sorted.sort_unstable_by_key(|&(x, y)| (y, x));
+
+    // side length of the largest square with its bottom right corner at each tile
+    let mut sizes = std::collections::HashMap::with_capacity(sorted.len());
+    let mut best = ((0, 0), 0);
+    for (x, y) in sorted {
+        let size_at = |tile: Tile| sizes.get(&tile).copied().unwrap_or(0);
+        let size = 1 + size_at((x.wrapping_sub(1), y))
+            .min(size_at((x, y.wrapping_sub(1))))
+            .min(size_at((x.wrapping_sub(1), y.wrapping_sub(1))));
+        if size > best.1 {
+            best = ((x + 1 - size, y + 1 - size), size);
+        }
+        sizes.insert((x, y), size);
+    }
+
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_tiles_test() {
+        let mut tiles = HashSet::new();
+        line_tiles((0.5, 0.5), (2.5, 1.5), 4, &mut tiles);
+        assert_eq!(tiles, HashSet::from([(0, 0), (1, 0), (1, 1), (2, 1)]));
+    }
+
+    #[test]
+    fn cluster_and_square_test() {
+        let mut tiles: HashSet<Tile> = (0..4).flat_map(|x| (0..4).map(move |y| (x, y))).collect();
+        tiles.insert((4, 1));
+        tiles.insert((9, 9));
+        assert_eq!(max_square(&tiles), ((0, 0), 4));
+        assert_eq!(
+            largest_cluster(&tiles),
+            HashSet::from([(1, 1), (2, 1), (1, 2), (2, 2), (3, 1)])
+        );
+    }
+}
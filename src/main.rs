@@ -3,66 +3,351 @@
 extern crate reqwest;
 
 use chrono::{DateTime, Utc};
-use image::{io::Reader as ImageReader, ImageFormat, Rgb};
+use image::{io::Reader as ImageReader, ImageFormat, Rgb, RgbImage};
+use std::fs;
 use std::io::Cursor;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process;
 #[cfg(target_os = "macos")]
 use std::process::Command;
-use structopt::StructOpt;
+use structopt::{clap, StructOpt};
 
 mod heatmap;
 
 #[derive(StructOpt)]
-#[structopt(name = "heatmap")]
+#[structopt(name = "heatmap", setting = clap::AppSettings::SubcommandsNegateReqs)]
+#[allow(clippy::doc_markdown)]
+#[allow(clippy::struct_excessive_bools)]
 struct Opt {
-    /// MapBox API Token
+    /// MapBox API Token (required to draw a map)
     #[structopt(short = "t", long = "token")]
-    access_token: String,
+    access_token: Option<String>,
+
+    /// Draw "© Mapbox © OpenStreetMap" attribution in the bottom right corner (replacing the attribution added by MapBox)
+    #[structopt(long)]
+    attribution: bool,
+
+    /// Aggregate tracks into hex or square bins of --bin-size colored by --ramp, instead of drawing lines
+    #[structopt(long)]
+    bins: Option<heatmap::bins::BinShape>,
+
+    /// Count every track point in a bin (points) or each track at most once per bin (visits)
+    #[structopt(long, default_value = "visits")]
+    bin_count: heatmap::bins::BinCount,
+
+    /// Opacity of bins
+    #[structopt(long, default_value = "0.7")]
+    bin_opacity: f64,
+
+    /// Distance in meters across each bin
+    #[structopt(long, default_value = "250")]
+    bin_size: f64,
+
+    /// Percentage of latitudes and longitudes (leaving out equal amounts of the lowest and highest) covered when automatically framing the map, so stray points don't stretch it. 100 covers every point
+    #[structopt(long, default_value = "99.9")]
+    bounds_percentile: f64,
 
     /// Minimum bounding box of generated map (instead of map growing to fit all points) as the decimal latitude & longitude of the northeast and southwest corners. e.g.: 40.799235,-73.943158,40.763277,-73.985393 (NElat,NElon,SWlat,SWlon)
     #[structopt(long = "box")]
     corners: Option<String>,
 
     /// Map biking tracks
-    #[structopt(long)]
+    #[structopt(long, global = true)]
     bike: bool,
 
     /// RGB Color used for heatmap
     #[structopt(short, long, default_value = "0,255,0")]
     color: String,
 
+    /// Center of the map as a decimal latitude & longitude (ex: 40.7812,-73.9665), framed by --radius or --zoom instead of the tracks
+    #[structopt(long, conflicts_with_all = &["corners", "cluster"])]
+    center: Option<heatmap::Point>,
+
+    /// Remove GPS glitches from tracks: points at 0,0, runs of up to 10 points that jump more than --clean-jump away and back, and runs of up to 10 points reached and left faster than the fastest plausible speed for the track's activity type
+    #[structopt(long, global = true)]
+    clean: bool,
+
+    /// Distance in meters of jumps away from a track and back removed by --clean
+    #[structopt(long, global = true, default_value = "500", parse(try_from_str = parse_positive))]
+    clean_jump: f64,
+
+    /// Fastest plausible speed for an activity type used by --clean, in the form of type=kph (ex: run=30), overriding the built-in speed (bike 120, ebike 80, virtualride 120, run 40, walk 20, hike 20, ski 160, swim 15, row 40, kayak 40, and 200 for unknown types). Can be repeated
+    #[structopt(long, global = true, number_of_values = 1, requires = "clean", parse(try_from_str = heatmap::clean::parse_max_speed))]
+    clean_speed: Vec<(heatmap::ActivityType, f64)>,
+
+    /// Render a separate map for each cluster of tracks that start within this many meters of another track in the cluster
+    #[structopt(long)]
+    cluster: Option<f64>,
+
+    /// Minimum number of tracks in a cluster for it to be rendered
+    #[structopt(long, default_value = "1")]
+    cluster_min_tracks: usize,
+
+    /// Compare tracks matching --start and --end with tracks that started before the end of this date (in any form accepted by --start), coloring pixels by which has more tracks
+    #[structopt(long)]
+    compare_end: Option<heatmap::period::Period>,
+
+    /// Compare tracks matching --start and --end with tracks that started after the start of this date (in any form accepted by --start), coloring pixels by which has more tracks
+    #[structopt(long)]
+    compare_start: Option<heatmap::period::Period>,
+
+    /// Report how much of the streets in this OSM extract (.osm XML, or .pbf) the tracks travel, and draw the streets they don't in --coverage-color
+    #[structopt(long, parse(from_os_str), conflicts_with = "panels")]
+    coverage: Option<PathBuf>,
+
+    /// Only count streets of --coverage with midpoints inside this circle (lat,lng,radius in meters), polygon (at least 3 lat,lng points separated by semicolons), or the polygons in this GeoJSON file
+    #[structopt(long)]
+    coverage_boundary: Option<String>,
+
+    /// RGB color of the streets --coverage draws that the tracks don't travel
+    #[structopt(long, default_value = "255,0,255", parse(try_from_str = heatmap::ramp::parse_color))]
+    coverage_color: Rgb<u8>,
+
+    /// Most meters from a track to the parts of streets it travels for --coverage
+    #[structopt(long, default_value = "25", parse(try_from_str = parse_positive))]
+    coverage_radius: f64,
+
+    /// Draw the date range of the mapped tracks (or of --start and --end) beneath the title
+    #[structopt(long)]
+    date_range: bool,
+
+    /// Don't map tracks with a GPX <name> or TCX <Notes> matching this regular expression (ex: "(?i)commute|zwift")
+    #[structopt(long, global = true)]
+    exclude_name: Option<regex::Regex>,
+
+    /// Skip files and directories with paths matching this glob pattern (ex: "*/zwift/*") when reading directories. Can be repeated
+    #[structopt(long, global = true, number_of_values = 1)]
+    exclude_path: Vec<glob::Pattern>,
+
+    /// Don't map tracks of this activity type (any type accepted by --type). Can be repeated
+    #[structopt(long, global = true, number_of_values = 1)]
+    exclude_type: Vec<heatmap::ActivityType>,
+
+    /// Drop tracks recorded at the same time as a track with more points that stay within this many meters of it (such as the same ride recorded by a watch and a bike computer), reporting each dropped track
+    #[structopt(long, global = true, parse(try_from_str = parse_positive))]
+    dedupe: Option<f64>,
+
+    /// Shade every explorer tile (slippy map tile at --explorer-zoom) visited by a track and report tile, max cluster, and max square counts
+    #[structopt(long)]
+    explorer: bool,
+
+    /// Zoom level of explorer tiles
+    #[structopt(long, default_value = "14")]
+    explorer_zoom: u8,
+
+    /// Export the grid of per-pixel track counts (not available with --bins) to a .csv (x,y,lat,lng,count rows), .npy (uint32 array with a .wld world file), or .tif (float32 GeoTIFF) file
+    #[structopt(long, parse(from_os_str))]
+    export_grid: Option<PathBuf>,
+
     /// Factor used in calculating heatmap pixel opacity (higher values will result in more opaque pixels)
     #[structopt(short, long, default_value = "1")]
     factor: f64,
 
+    /// Most meters between consecutive points that are joined by a line (inf to never break lines by distance)
+    #[structopt(long, global = true, default_value = "inf", parse(try_from_str = parse_positive))]
+    gap_meters: f64,
+
+    /// Most seconds between consecutive timestamped points that are joined by a line, counting whole seconds (so the default joins gaps under 6 seconds; inf to never break lines by time)
+    #[structopt(long, global = true, default_value = "5", parse(try_from_str = parse_non_negative))]
+    gap_seconds: f64,
+
+    /// Only map tracks passing through this circle (lat,lng,radius in meters), polygon (at least 3 lat,lng points separated by semicolons), or the polygons in this GeoJSON file
+    #[structopt(long, global = true)]
+    geofence: Option<String>,
+
+    /// Only map the parts of tracks inside --geofence
+    #[structopt(long, global = true, requires = "geofence")]
+    geofence_clip: bool,
+
     /// Input GPX/TCX files and directories
     #[structopt(name = "file list", parse(from_os_str))]
     file_list: Vec<PathBuf>,
 
-    /// Only map tracks that started before this date
+    /// Only map tracks that started before the end of this date (in any form accepted by --start), so --end 2023 includes all of 2023
+    #[structopt(long, global = true)]
+    end: Option<heatmap::period::Period>,
+
+    /// Draw a legend of the heatmap color ramp
     #[structopt(long)]
-    end: Option<String>,
+    legend: bool,
+
+    /// Snap tracks onto the streets and paths of this OSM extract (.osm XML, or .pbf) with hidden Markov model map matching
+    #[structopt(long, global = true, parse(from_os_str))]
+    match_roads: Option<PathBuf>,
+
+    /// Most meters from a track point to the road it's snapped to with --match-roads, with points further from every road left as recorded
+    #[structopt(long, global = true, default_value = "30", parse(try_from_str = parse_positive))]
+    match_radius: f64,
 
     /// Mapbox style used for map image
     #[structopt(long = "style", default_value = "mapbox/dark-v10")]
     mapbox_style: String,
 
+    /// Only map tracks moving at an average of at most this many km/h
+    #[structopt(long, global = true, parse(try_from_str = parse_non_negative))]
+    max_avg_speed: Option<f64>,
+
+    /// Only map tracks at most this many meters long
+    #[structopt(long, global = true, parse(try_from_str = parse_non_negative))]
+    max_distance: Option<f64>,
+
+    /// Only map tracks lasting at most this many minutes from start to finish
+    #[structopt(long, global = true, parse(try_from_str = parse_non_negative))]
+    max_duration: Option<f64>,
+
+    /// Only map tracks moving for at most this many minutes
+    #[structopt(long, global = true, parse(try_from_str = parse_non_negative))]
+    max_moving_time: Option<f64>,
+
+    /// Only map tracks never going faster than this many km/h between points (ex: to leave out car rides)
+    #[structopt(long, global = true, parse(try_from_str = parse_non_negative))]
+    max_top_speed: Option<f64>,
+
     /// Minimum opacity of any track pixel that has at least 1 track on it
     #[structopt(short, long, default_value = "0.25")]
     min: f64,
 
-    /// Map running tracks
+    /// Only map tracks moving at an average of at least this many km/h
+    #[structopt(long, global = true, parse(try_from_str = parse_non_negative))]
+    min_avg_speed: Option<f64>,
+
+    /// Only map tracks at least this many meters long
+    #[structopt(long, global = true, parse(try_from_str = parse_non_negative))]
+    min_distance: Option<f64>,
+
+    /// Only map tracks lasting at least this many minutes from start to finish
+    #[structopt(long, global = true, parse(try_from_str = parse_non_negative))]
+    min_duration: Option<f64>,
+
+    /// Only map tracks moving for at least this many minutes
+    #[structopt(long, global = true, parse(try_from_str = parse_non_negative))]
+    min_moving_time: Option<f64>,
+
+    /// Only map tracks that started in these months, as numbers, names, ranges (ex: nov-feb), or seasons (winter, spring, summer, or fall), separated by commas
+    #[structopt(long, global = true)]
+    months: Option<heatmap::schedule::Months>,
+
+    /// Draw a grid of panels, one per year, month, or activity type (after --type-mapping, and only the types that have tracks; tracks without a known type are left out), sharing the same map and track color scale
     #[structopt(long)]
+    panels: Option<heatmap::panels::PanelBy>,
+
+    /// Hide track points inside this circle (lat,lng,radius in meters) or polygon (at least 3 lat,lng points separated by semicolons). May be given multiple times
+    #[structopt(long, global = true, number_of_values = 1)]
+    privacy_zone: Vec<heatmap::region::Region>,
+
+    /// Color ramp used for bins and comparisons, either heat, viridis, blues, diverging, or a list of r,g,b colors separated by semicolons [default: heat for bins, diverging for comparisons]
+    #[structopt(long)]
+    ramp: Option<heatmap::ramp::Ramp>,
+
+    /// Replace track points with points every this many meters along each track, after --simplify (lines broken by --gap-seconds or --gap-meters are never joined)
+    #[structopt(long, global = true, parse(try_from_str = parse_positive))]
+    resample: Option<f64>,
+
+    /// Map running tracks
+    #[structopt(long, global = true)]
     run: bool,
 
-    /// Only map tracks that started after this date
+    /// Drop track points that barely change the shape of tracks, keeping points further than this many meters from a simplified track (though lines broken by --gap-seconds or --gap-meters are never joined, and unbroken lines stay unbroken)
+    #[structopt(long, global = true, parse(try_from_str = parse_positive))]
+    simplify: Option<f64>,
+
+    /// Algorithm used by --simplify, either dp (Douglas-Peucker) or vw (Visvalingam-Whyatt, dropping points that form triangles smaller than --simplify squared with their neighbors)
+    #[structopt(long, global = true, default_value = "dp")]
+    simplify_method: heatmap::simplify::Method,
+
+    /// Split tracks into separate tracks wherever --gap-seconds or --gap-meters break their lines, before clustering, de-duplicating, and clipping
+    #[structopt(long, global = true)]
+    split_gaps: bool,
+
+    /// Draw a scale bar
     #[structopt(long)]
-    start: Option<String>,
+    scale_bar: bool,
 
-    /// Map walking tracks
+    /// Distance in meters from --center to the nearest edges of the map
+    #[structopt(long, requires = "center", conflicts_with = "zoom", parse(try_from_str = parse_positive))]
+    radius: Option<f64>,
+
+    /// Only map tracks that started after the start of this date, which is a date (ex: 2024-01-01), month (2024-06), year (2024), RFC 3339 date-time (2024-01-01T08:00:00Z), length of time up to now (30d, 2w, 6m, or 1y), today, yesterday, this-month, last-month, this-year, last-year, or ytd, in UTC
+    #[structopt(long, global = true)]
+    start: Option<heatmap::period::Period>,
+
+    /// Only map tracks that started within this local time of day, as start-end in hours or hours:minutes (ex: 6:30-9), wrapping past midnight if end is before start
+    #[structopt(long, global = true)]
+    time_of_day: Option<heatmap::schedule::TimeWindow>,
+
+    /// Title drawn in the top left corner of the map
     #[structopt(long)]
+    title: Option<String>,
+
+    /// Hide this many meters from the start and end of every track
+    #[structopt(long, global = true, default_value = "0", parse(try_from_str = parse_non_negative))]
+    trim_ends: f64,
+
+    /// Timezone used for --time-of-day, --weekdays, --months, and year or month --panels, either a name like America/Chicago or auto (whole hours from UTC based on the longitude of each track, ignoring daylight saving time)
+    #[structopt(long, global = true, default_value = "auto")]
+    tz: heatmap::schedule::Zone,
+
+    /// Map tracks of this activity type (bike, ebike, virtualride, run, walk, hike, ski, swim, row, or kayak), which can be repeated and combined with --bike, --run, and --walk
+    #[structopt(long = "type", global = true, number_of_values = 1)]
+    activity_types: Vec<heatmap::ActivityType>,
+
+    /// Read this GPX <type> or TCX Sport name as an activity type, in the form of name=type (ex: SUP=kayak), overriding the built-in Strava, Garmin, Komoot, and RideWithGPS names. Can be repeated
+    #[structopt(long, global = true, number_of_values = 1, parse(try_from_str = heatmap::activity::parse_mapping))]
+    type_mapping: Vec<(String, heatmap::ActivityType)>,
+
+    /// Units used for the scale bar (metric or imperial)
+    #[structopt(long, default_value = "metric")]
+    units: heatmap::annotate::Units,
+
+    /// Map walking tracks
+    #[structopt(long, global = true)]
     walk: bool,
+
+    /// Only map tracks that started on these local days of the week, as names (ex: mon), ranges (ex: sat-sun), weekdays, or weekends, separated by commas
+    #[structopt(long, global = true)]
+    weekdays: Option<heatmap::schedule::Weekdays>,
+
+    /// Only map tracks that started in this year (the same as --start and --end of the year)
+    #[structopt(long, global = true, conflicts_with_all = &["start", "end"], parse(try_from_str = parse_year))]
+    year: Option<heatmap::period::Period>,
+
+    /// MapBox zoom level of the map around --center (0 to 22)
+    #[structopt(long, requires = "center", parse(try_from_str = parse_zoom))]
+    zoom: Option<f64>,
+
+    #[structopt(subcommand)]
+    command: Option<Subcommand>,
+}
+
+/// Ways to report on the loaded tracks instead of drawing a map
+/// The options that choose and clean up tracks can be given before or after the subcommand
+#[derive(StructOpt)]
+enum Subcommand {
+    /// Print each file's format, activity type, start time, duration, distance, number of points, box, and any problems reading it or reasons it would be left out
+    List {
+        /// Print a table, csv, or json
+        #[structopt(long, default_value = "table")]
+        format: heatmap::stats::Format,
+
+        /// Files or directories of tracks
+        #[structopt(name = "file list", required = true, parse(from_os_str))]
+        file_list: Vec<PathBuf>,
+    },
+
+    /// Print the number of tracks, distance, moving time, elevation gain, longest track, and first and last dates for each activity type and period
+    Stats {
+        /// Total tracks by year, month, or all together
+        #[structopt(long, default_value = "year")]
+        by: heatmap::stats::GroupBy,
+
+        /// Print a table, csv, or json
+        #[structopt(long, default_value = "table")]
+        format: heatmap::stats::Format,
+
+        /// Files or directories of tracks
+        #[structopt(name = "file list", required = true, parse(from_os_str))]
+        file_list: Vec<PathBuf>,
+    },
 }
 
 #[allow(clippy::too_many_lines)]
@@ -70,6 +355,22 @@ struct Opt {
 async fn main() {
     let opt = Opt::from_args();
 
+    if opt.command.is_none() && opt.access_token.is_none() {
+        clap::Error::with_description(
+            "--token is required to draw a map",
+            clap::ErrorKind::MissingRequiredArgument,
+        )
+        .exit();
+    }
+
+    if opt.center.is_some() && opt.radius.is_none() && opt.zoom.is_none() {
+        clap::Error::with_description(
+            "--center requires either --radius or --zoom",
+            clap::ErrorKind::MissingRequiredArgument,
+        )
+        .exit();
+    }
+
     let color: Vec<u8> = opt
         .color
         .split(',')
@@ -89,120 +390,710 @@ async fn main() {
         process::exit(1);
     }
 
-    let start = opt.start.map(|start| {
-        start
-            .parse::<DateTime<Utc>>()
-            .expect("Unable to parse start into date")
+    if opt.bin_size <= 0.0 {
+        eprintln!("bin-size must be greater than 0");
+        process::exit(1);
+    }
+
+    if opt.bins.is_some() && opt.export_grid.is_some() {
+        eprintln!("--export-grid can't be used with --bins");
+        process::exit(1);
+    }
+
+    let comparing = opt.compare_start.is_some() || opt.compare_end.is_some();
+    if comparing && (opt.bins.is_some() || opt.export_grid.is_some()) {
+        eprintln!("--compare-start and --compare-end can't be used with --bins or --export-grid");
+        process::exit(1);
+    }
+
+    if opt.panels.is_some()
+        && (comparing || opt.bins.is_some() || opt.explorer || opt.export_grid.is_some())
+    {
+        eprintln!("--panels can't be used with --bins, --compare-start, --compare-end, --explorer, or --export-grid");
+        process::exit(1);
+    }
+
+    if opt.bounds_percentile <= 0.0 || opt.bounds_percentile > 100.0 {
+        eprintln!("bounds-percentile must be greater than 0 and at most 100");
+        process::exit(1);
+    }
+
+    if opt.cluster.is_some_and(|distance| distance <= 0.0) {
+        eprintln!("cluster must be greater than 0");
+        process::exit(1);
+    }
+
+    if opt.cluster.is_some()
+        && (comparing || opt.corners.is_some() || opt.export_grid.is_some() || opt.panels.is_some())
+    {
+        eprintln!("--cluster can't be used with --box, --compare-start, --compare-end, --export-grid, or --panels");
+        process::exit(1);
+    }
+
+    if opt.explorer_zoom > 20 {
+        eprintln!("explorer-zoom must be at most 20");
+        process::exit(1);
+    }
+
+    let start = opt.start.or(opt.year).map(|period| period.start);
+    let end = opt.end.or(opt.year).map(|period| period.end);
+    let compare_start = opt.compare_start.map(|period| period.start);
+    let compare_end = opt.compare_end.map(|period| period.end);
+    if let (Some(start), Some(end)) = (start, end) {
+        if start >= end {
+            eprintln!("--start must be before --end");
+            process::exit(1);
+        }
+    }
+
+    let types = if opt.bike || opt.run || opt.walk || !opt.activity_types.is_empty() {
+        let mut types = Vec::new();
+        let flags = [
+            (opt.bike, heatmap::ActivityType::Bike),
+            (opt.run, heatmap::ActivityType::Run),
+            (opt.walk, heatmap::ActivityType::Walk),
+        ];
+        let flagged = flags.iter().filter(|(set, _)| *set).map(|&(_, t)| t);
+        for activity in opt.activity_types.iter().copied().chain(flagged) {
+            if !types.contains(&activity) {
+                types.push(activity);
+            }
+        }
+        Some(types)
+    } else {
+        None
+    };
+
+    let gap = heatmap::GapRule {
+        seconds: opt.gap_seconds,
+        meters: opt.gap_meters,
+    };
+
+    let cleaning = opt.clean.then(|| heatmap::clean::Cleaning {
+        max_speeds: opt.clean_speed.clone(),
+        max_jump: opt.clean_jump,
     });
-    let end = opt.end.map(|end| {
-        end.parse::<DateTime<Utc>>()
-            .expect("Unable to parse end into date")
+
+    let simplify =
+        (opt.simplify.is_some() || opt.resample.is_some()).then(|| heatmap::simplify::Simplify {
+            method: opt
+                .simplify
+                .map(|tolerance| (opt.simplify_method, tolerance)),
+            resample: opt.resample,
+            gap,
+        });
+
+    let roads = opt
+        .match_roads
+        .as_ref()
+        .map(|path| read_roads(path, "--match-roads"));
+    let matcher = roads.as_ref().map(|roads| heatmap::matching::Matcher {
+        roads,
+        radius: opt.match_radius,
+        gap,
     });
 
-    let filters = if opt.bike || opt.run || opt.walk {
-        let mut filters = Vec::new();
-        if opt.bike {
-            filters.push(heatmap::ActivityType::Bike);
+    let mapping = heatmap::activity::Mapping {
+        custom: opt.type_mapping.clone(),
+    };
+
+    let geofence = opt
+        .geofence
+        .as_ref()
+        .map(|geofence| heatmap::region::Geofence {
+            regions: read_regions(geofence, "--geofence"),
+            clip: opt.geofence_clip,
+        });
+    let filters = heatmap::Filters {
+        types: types.as_deref(),
+        mapping: Some(&mapping),
+        exclude_types: &opt.exclude_type,
+        exclude_name: opt.exclude_name.as_ref(),
+        exclude_paths: &opt.exclude_path,
+        cleaning: cleaning.as_ref(),
+        split_gaps: opt.split_gaps.then_some(gap),
+        matcher: matcher.as_ref(),
+        simplify: simplify.as_ref(),
+        start,
+        end,
+        geofence: geofence.as_ref(),
+        limits: heatmap::metrics::Limits {
+            distance: (opt.min_distance, opt.max_distance),
+            elapsed: (minutes(opt.min_duration), minutes(opt.max_duration)),
+            moving: (minutes(opt.min_moving_time), minutes(opt.max_moving_time)),
+            average_speed: (kph(opt.min_avg_speed), kph(opt.max_avg_speed)),
+            max_speed: kph(opt.max_top_speed),
+        },
+        schedule: heatmap::schedule::Schedule {
+            zone: opt.tz,
+            time_of_day: opt.time_of_day,
+            weekdays: opt.weekdays,
+            months: opt.months,
+        },
+    };
+
+    if let Some(Subcommand::List { format, file_list }) = &opt.command {
+        let entries = heatmap::list::entries(file_list, &filters);
+        print!("{}", heatmap::list::format(&entries, *format));
+        return;
+    }
+
+    let privacy = heatmap::privacy::Privacy {
+        zones: opt.privacy_zone,
+        trim: opt.trim_ends,
+    };
+    let file_list = match &opt.command {
+        Some(Subcommand::List { file_list, .. } | Subcommand::Stats { file_list, .. }) => file_list,
+        None => &opt.file_list,
+    };
+    // loads tracks matching filters (with the activity types of their files), without duplicates and with private parts hidden
+    let load_typed = |filters: &heatmap::Filters| {
+        let mut tracks = heatmap::get_pts_from_files(file_list, filters);
+        if let Some(distance) = opt.dedupe {
+            let (activities, trk_pts): (Vec<_>, Vec<_>) = tracks.into_iter().unzip();
+            let (dropped, duplicates) = heatmap::dedupe::find_duplicates(&trk_pts, distance);
+            for duplicate in &duplicates {
+                eprintln!(
+                    "Dropped duplicate track starting {} with {} points (kept one with {} points)",
+                    duplicate.start.format("%Y-%m-%d %H:%M:%S"),
+                    duplicate.points,
+                    duplicate.kept_points
+                );
+            }
+            if !duplicates.is_empty() {
+                eprintln!("Dropped {} duplicate tracks", duplicates.len());
+            }
+            tracks = activities
+                .into_iter()
+                .zip(trk_pts)
+                .zip(dropped)
+                .filter_map(|(track, dropped)| (!dropped).then_some(track))
+                .collect();
         }
-        if opt.run {
-            filters.push(heatmap::ActivityType::Run);
+        tracks
+            .into_iter()
+            .flat_map(|(activity, pts)| {
+                privacy
+                    .apply(vec![pts])
+                    .into_iter()
+                    .map(move |pts| (activity, pts))
+            })
+            .collect::<Vec<_>>()
+    };
+    let load = |filters: &heatmap::Filters| {
+        load_typed(filters)
+            .into_iter()
+            .map(|(_, pts)| pts)
+            .collect::<Vec<_>>()
+    };
+
+    if let Some(Subcommand::Stats { by, format, .. }) = opt.command {
+        let tracks = load_typed(&filters);
+        let mut rows = Vec::new();
+        for &activity in filters.types.unwrap_or(&heatmap::ActivityType::ALL) {
+            let trk_pts = tracks
+                .iter()
+                .filter(|&&(track_activity, _)| track_activity == Some(activity))
+                .map(|(_, pts)| pts);
+            rows.append(&mut heatmap::stats::rows(
+                &activity.to_string(),
+                trk_pts,
+                by,
+                opt.tz,
+            ));
+        }
+        // tracks of every type together, including ones without a known type
+        rows.append(&mut heatmap::stats::rows(
+            "All",
+            tracks.iter().map(|(_, pts)| pts),
+            by,
+            opt.tz,
+        ));
+        print!("{}", heatmap::stats::format(&rows, format));
+        return;
+    }
+
+    let (mut trk_pts, panels) = match opt.panels {
+        Some(heatmap::panels::PanelBy::Activity) => {
+            let (trk_pts, panels) =
+                heatmap::panels::by_activity(load_typed(&filters), filters.types);
+            (trk_pts, Some(panels))
         }
-        if opt.walk {
-            filters.push(heatmap::ActivityType::Walk);
+        Some(by) => {
+            let mut trk_pts = load(&filters);
+            let format = if let heatmap::panels::PanelBy::Year = by {
+                "%Y"
+            } else {
+                "%Y-%m"
+            };
+            let panels = heatmap::panels::by_date(&mut trk_pts, format, opt.tz);
+            (trk_pts, Some(panels))
         }
-        Some(filters)
+        None => (load(&filters), None),
+    };
+    // tracks compared against trk_pts in a difference map
+    let compare_pts = if comparing {
+        Some(load(&heatmap::Filters {
+            start: compare_start,
+            end: compare_end,
+            ..filters
+        }))
     } else {
         None
     };
 
-    let trk_pts = heatmap::get_pts_from_files(&opt.file_list, &filters, &start, &end);
-
-    if trk_pts.is_empty() {
+    if trk_pts.is_empty() && compare_pts.as_ref().is_none_or(Vec::is_empty) {
         eprintln!("No valid files loaded");
         process::exit(2);
     }
 
-    // calculate min and max points, or try to parse specified values
-    let (min, max) = if let Some(corners) = opt.corners {
-        let corners: Vec<&str> = corners.split(',').collect();
-        if corners.len() != 4 {
-            eprintln!("--box must be 4 comma sepearated values");
-            process::exit(1);
+    if panels.as_ref().is_some_and(Vec::is_empty) {
+        if let Some(heatmap::panels::PanelBy::Activity) = opt.panels {
+            eprintln!("No tracks with known activity types to split into panels");
+        } else {
+            eprintln!("No tracks with timestamps to split into panels");
         }
-        let max_lat = parse_lat_lng(corners[0]);
-        let max_lng = parse_lat_lng(corners[1]);
-        let min_lat = parse_lat_lng(corners[2]);
-        let min_lng = parse_lat_lng(corners[3]);
-        if max_lat <= min_lat || max_lng <= min_lng {
-            eprintln!("first coordinate of --box must be strictly greater than second coordinate");
-            process::exit(1);
+        process::exit(2);
+    }
+
+    // groups of trk_pts rendered to separate maps
+    let regions = if let Some(distance) = opt.cluster {
+        let clusters = heatmap::cluster::by_start(&mut trk_pts, distance, opt.cluster_min_tracks);
+        if clusters.is_empty() {
+            eprintln!(
+                "No clusters with at least {} tracks",
+                opt.cluster_min_tracks
+            );
+            process::exit(2);
         }
-        (
-            heatmap::Point {
-                lat: min_lat,
-                lng: min_lng,
-            },
-            heatmap::Point {
-                lat: max_lat,
-                lng: max_lng,
-            },
-        )
+        clusters
     } else {
-        heatmap::min_max(&trk_pts)
+        std::iter::once(0..trk_pts.len()).collect()
     };
 
-    let pixels = 1280;
-    let map_info = heatmap::calculate_map(pixels, &min, &max, 2.0);
-    // get mapbox static API image based on center and zoom level from map_info
-    let mapbox_response = reqwest::get(&format!(
-        "https://api.mapbox.com/styles/v1/{}/static/{},{},{}/{4}x{4}@2x?access_token={5}",
-        opt.mapbox_style,
-        map_info.center.lng,
-        map_info.center.lat,
-        map_info.zoom,
-        pixels,
-        opt.access_token
-    ))
-    .await
-    .expect("Error GETing mapbox image");
-    assert!(
-        mapbox_response.status().is_success(),
-        "Non success response code {} from mapbox",
-        mapbox_response.status()
-    );
-    // load mapbox response into image buffer
-    let mapbox_bytes = mapbox_response
-        .bytes()
+    let coverage_roads = opt
+        .coverage
+        .as_ref()
+        .map(|path| read_roads(path, "--coverage"));
+    let coverage_boundary = opt
+        .coverage_boundary
+        .as_ref()
+        .map_or_else(Vec::new, |boundary| {
+            read_regions(boundary, "--coverage-boundary")
+        });
+
+    let track_color = Rgb([color[0], color[1], color[2]]);
+    let ramp = opt.ramp.unwrap_or_else(|| {
+        let default = if comparing { "diverging" } else { "heat" };
+        default.parse().expect("default ramp must be valid")
+    });
+    let timestamp = Utc::now().timestamp();
+
+    for (i, region) in regions.into_iter().enumerate() {
+        let trk_pts = &trk_pts[region];
+        if opt.cluster.is_some() {
+            let (min, max) = heatmap::min_max(trk_pts);
+            println!(
+                "Cluster {}: {} tracks between {min:?} and {max:?}",
+                i + 1,
+                trk_pts.len()
+            );
+        }
+
+        // panels share the width of a single map
+        let pixels = panels
+            .as_ref()
+            .map_or(1280, |panels| 1280 / heatmap::panels::columns(panels.len()));
+        let map_info = if let (Some(center), Some(zoom)) = (&opt.center, opt.zoom) {
+            heatmap::calculate_map_at_zoom(pixels, center, zoom, 2.0)
+        } else {
+            // calculate min and max points, or try to parse specified values
+            let (min, max) = if let Some(corners) = &opt.corners {
+                let corners: Vec<&str> = corners.split(',').collect();
+                if corners.len() != 4 {
+                    eprintln!("--box must be 4 comma sepearated values");
+                    process::exit(1);
+                }
+                let max_lat = parse_lat_lng(corners[0]);
+                let max_lng = parse_lat_lng(corners[1]);
+                let min_lat = parse_lat_lng(corners[2]);
+                let min_lng = parse_lat_lng(corners[3]);
+                // a northeast corner west of the southwest corner means the box crosses the antimeridian
+                let max_lng = if max_lng < min_lng {
+                    max_lng + 360.0
+                } else {
+                    max_lng
+                };
+                if max_lat <= min_lat || max_lng <= min_lng {
+                    eprintln!(
+                        "first coordinate of --box must be strictly greater than second coordinate"
+                    );
+                    process::exit(1);
+                }
+                (
+                    heatmap::Point {
+                        lat: min_lat,
+                        lng: min_lng,
+                    },
+                    heatmap::Point {
+                        lat: max_lat,
+                        lng: max_lng,
+                    },
+                )
+            } else if let (Some(center), Some(radius)) = (&opt.center, opt.radius) {
+                // box just containing the circle of radius around center
+                (
+                    heatmap::Point {
+                        lat: heatmap::destination(center, 180.0, radius).lat,
+                        lng: heatmap::destination(center, 270.0, radius).lng,
+                    },
+                    heatmap::Point {
+                        lat: heatmap::destination(center, 0.0, radius).lat,
+                        lng: heatmap::destination(center, 90.0, radius).lng,
+                    },
+                )
+            } else {
+                heatmap::percentile_bounds(
+                    trk_pts.iter().chain(compare_pts.iter().flatten()),
+                    opt.bounds_percentile,
+                )
+            };
+
+            heatmap::calculate_map(pixels, &min, &max, 2.0)
+        };
+        // get mapbox static API image based on center and zoom level from map_info
+        let mapbox_response = reqwest::get(&format!(
+            "https://api.mapbox.com/styles/v1/{}/static/{},{},{}/{4}x{4}@2x?attribution={5}&access_token={6}",
+            opt.mapbox_style,
+            map_info.center.lng,
+            map_info.center.lat,
+            map_info.zoom,
+            pixels,
+            !opt.attribution,
+            opt.access_token
+                .as_deref()
+                .expect("token must be given to draw a map")
+        ))
         .await
-        .expect("Error getting bytes from mapbox response");
-    let png_reader = ImageReader::with_format(Cursor::new(mapbox_bytes), ImageFormat::Png);
-    let map_image = png_reader
-        .decode()
-        .expect("Error decoding mapbox response")
-        .to_rgb8();
-
-    // overlay path from trk_pts onto map image
-    let heatmap_image = heatmap::overlay_image(
-        map_image,
-        &map_info,
-        &trk_pts,
-        Rgb([color[0], color[1], color[2]]),
-        opt.factor,
-        opt.min,
-    );
-
-    let image_filename = format!("heatmap_{}.png", Utc::now().timestamp());
-    heatmap_image
-        .save(&image_filename)
-        .expect("Error saving final png");
-
-    #[cfg(target_os = "macos")]
-    {
-        // open image in preview
-        Command::new("open")
-            .args(&[&image_filename])
-            .output()
-            .unwrap_or_else(|e| panic!("Failed to open {}\n{}", image_filename, e));
+        .expect("Error GETing mapbox image");
+        assert!(
+            mapbox_response.status().is_success(),
+            "Non success response code {} from mapbox",
+            mapbox_response.status()
+        );
+        // load mapbox response into image buffer
+        let mapbox_bytes = mapbox_response
+            .bytes()
+            .await
+            .expect("Error getting bytes from mapbox response");
+        let png_reader = ImageReader::with_format(Cursor::new(mapbox_bytes), ImageFormat::Png);
+        let mut map_image = png_reader
+            .decode()
+            .expect("Error decoding mapbox response")
+            .to_rgb8();
+
+        if opt.explorer {
+            let explorer = heatmap::tiles::explore(trk_pts, opt.explorer_zoom, &gap);
+            let (_, square_size) = explorer.square;
+            println!(
+                "Explorer tiles (zoom {}): {} -- Max cluster: {} -- Max square: {square_size}x{square_size}",
+                explorer.zoom,
+                explorer.tiles.len(),
+                explorer.cluster.len()
+            );
+            heatmap::tiles::draw_tiles(&mut map_image, &map_info, &explorer, track_color);
+        }
+
+        let date_range = if opt.date_range {
+            let data_range = heatmap::time_range(trk_pts);
+            if let (Some(first), Some(last)) = (
+                start.or_else(|| data_range.map(|(first, _)| first)),
+                // --end is exclusive, so show the last day before it
+                end.map(|end| end - chrono::Duration::milliseconds(1))
+                    .or_else(|| data_range.map(|(_, last)| last)),
+            ) {
+                Some(format!(
+                    "{} - {}",
+                    first.format("%Y-%m-%d"),
+                    last.format("%Y-%m-%d")
+                ))
+            } else {
+                eprintln!("No track timestamps available for --date-range");
+                None
+            }
+        } else {
+            None
+        };
+
+        let mut heatmap_image = if let Some(shape) = opt.bins {
+            // aggregate trk_pts into bins and color them onto map image
+            let mut bins = heatmap::bins::Bins::new(shape, opt.bin_size, &map_info.center);
+            bins.add(trk_pts, opt.bin_count);
+            println!(
+                "Tracks: {} -- Bins: {} -- Max: {}",
+                trk_pts.len(),
+                bins.counts.len(),
+                bins.max()
+            );
+            heatmap::bins::draw_bins(&mut map_image, &map_info, &bins, &ramp, opt.bin_opacity);
+            if opt.legend {
+                let caption = match opt.bin_count {
+                    heatmap::bins::BinCount::Points => "Points",
+                    heatmap::bins::BinCount::Visits => "Tracks",
+                };
+                heatmap::annotate::draw_bin_legend(&mut map_image, caption, &ramp, bins.max());
+            }
+            map_image
+        } else if let Some(panels) = &panels {
+            // draw each panel with the same track color scale, then arrange them in a grid
+            let grids: Vec<Vec<Vec<u32>>> = panels
+                .iter()
+                .map(|panel| {
+                    heatmap::density(
+                        &map_info,
+                        map_image.width(),
+                        map_image.height(),
+                        &trk_pts[panel.tracks.clone()],
+                        &gap,
+                    )
+                })
+                .collect();
+            let grid_refs: Vec<&[Vec<u32>]> = grids.iter().map(Vec::as_slice).collect();
+            let single_step = heatmap::single_step(&grid_refs, opt.factor);
+            let images: Vec<RgbImage> = panels
+                .iter()
+                .zip(&grids)
+                .map(|(panel, factors)| {
+                    let mut image = heatmap::overlay_image(
+                        map_image.clone(),
+                        factors,
+                        track_color,
+                        single_step,
+                        opt.min,
+                    );
+                    heatmap::annotate::draw_title(
+                        &mut image,
+                        Some(&panel.label),
+                        Some(&format!("{} tracks", panel.tracks.len())),
+                    );
+                    image
+                })
+                .collect();
+            println!(
+                "Tracks: {} -- Panels: {} -- Step: {single_step:.2}",
+                trk_pts.len(),
+                panels.len()
+            );
+
+            // leave room above the panels for the title so it doesn't cover the first panel's label
+            let header =
+                heatmap::annotate::title_height(opt.title.as_deref(), date_range.as_deref());
+            let mut heatmap_image =
+                heatmap::panels::compose(&images, heatmap::panels::columns(panels.len()), header);
+            if opt.legend {
+                heatmap::annotate::draw_legend(
+                    &mut heatmap_image,
+                    track_color,
+                    opt.min,
+                    single_step,
+                );
+            }
+            heatmap_image
+        } else if let Some(compare_pts) = &compare_pts {
+            // color pixels by the difference in tracks between the two periods
+            let before = heatmap::density(
+                &map_info,
+                map_image.width(),
+                map_image.height(),
+                trk_pts,
+                &gap,
+            );
+            let after = heatmap::density(
+                &map_info,
+                map_image.width(),
+                map_image.height(),
+                compare_pts,
+                &gap,
+            );
+            let (mut heatmap_image, changes) = heatmap::diff::overlay_difference(
+                map_image, &before, &after, &ramp, opt.factor, opt.min,
+            );
+            println!(
+                "Tracks: {} vs {} -- Abandoned pixels: {} -- Explored pixels: {}",
+                trk_pts.len(),
+                compare_pts.len(),
+                changes.abandoned,
+                changes.explored
+            );
+            if opt.legend {
+                heatmap::annotate::draw_difference_legend(
+                    &mut heatmap_image,
+                    &ramp,
+                    &period_label(start, end),
+                    &period_label(compare_start, compare_end),
+                );
+            }
+            heatmap_image
+        } else {
+            let factors = heatmap::density(
+                &map_info,
+                map_image.width(),
+                map_image.height(),
+                trk_pts,
+                &gap,
+            );
+            if let Some(path) = &opt.export_grid {
+                if let Err(e) = heatmap::export::export_grid(path, &factors, &map_info) {
+                    eprintln!("Error exporting grid to {}: {e}", path.display());
+                }
+            }
+
+            // overlay path from trk_pts onto map image
+            let single_step = heatmap::single_step(&[&factors], opt.factor);
+            let mut heatmap_image =
+                heatmap::overlay_image(map_image, &factors, track_color, single_step, opt.min);
+            println!("Tracks: {} -- Step: {single_step:.2}", trk_pts.len());
+            if opt.legend {
+                heatmap::annotate::draw_legend(
+                    &mut heatmap_image,
+                    track_color,
+                    opt.min,
+                    single_step,
+                );
+            }
+            heatmap_image
+        };
+
+        if let Some(roads) = &coverage_roads {
+            let coverage = heatmap::coverage::Coverage::new(
+                roads,
+                &coverage_boundary,
+                trk_pts,
+                opt.coverage_radius,
+                &gap,
+            );
+            let traveled = coverage
+                .segments
+                .iter()
+                .filter(|(_, pieces)| pieces.iter().all(|&piece| piece))
+                .count();
+            println!(
+                "Street coverage: {:.1} of {:.1} km ({:.1}%) -- Fully traveled segments: {traveled} of {}",
+                coverage.covered / 1000.0,
+                coverage.length / 1000.0,
+                coverage.percent(),
+                coverage.segments.len()
+            );
+            coverage.draw_uncovered(&mut heatmap_image, &map_info, roads, opt.coverage_color);
+        }
+
+        // draw requested overlays onto the finished heatmap
+        heatmap::annotate::draw_title(
+            &mut heatmap_image,
+            opt.title.as_deref(),
+            date_range.as_deref(),
+        );
+        if opt.scale_bar {
+            heatmap::annotate::draw_scale_bar(&mut heatmap_image, &map_info, opt.units);
+        }
+        if opt.attribution {
+            heatmap::annotate::draw_attribution(&mut heatmap_image, "© Mapbox © OpenStreetMap");
+        }
+
+        let image_filename = if opt.cluster.is_some() {
+            format!("heatmap_{timestamp}_{}.png", i + 1)
+        } else {
+            format!("heatmap_{timestamp}.png")
+        };
+        heatmap_image
+            .save(&image_filename)
+            .expect("Error saving final png");
+
+        #[cfg(target_os = "macos")]
+        {
+            // open image in preview
+            Command::new("open")
+                .args(&[&image_filename])
+                .output()
+                .unwrap_or_else(|e| panic!("Failed to open {}\n{}", image_filename, e));
+        }
+    }
+}
+
+/// Converts optional `minutes` to seconds
+fn minutes(minutes: Option<f64>) -> Option<f64> {
+    minutes.map(|minutes| minutes * 60.0)
+}
+
+/// Converts optional speed `kph` in kilometers per hour to meters per second
+fn kph(kph: Option<f64>) -> Option<f64> {
+    kph.map(|kph| kph / 3.6)
+}
+
+/// Reads either a `GeoJSON` file or a single region from the value `arg` of `option`, exiting if it's invalid
+fn read_regions(arg: &str, option: &str) -> Vec<heatmap::region::Region> {
+    let regions = if Path::new(arg).is_file() {
+        fs::read_to_string(arg)
+            .map_err(Into::into)
+            .and_then(|contents| heatmap::region::from_geojson(&contents))
+    } else {
+        arg.parse::<heatmap::region::Region>()
+            .map(|region| vec![region])
+            .map_err(Into::into)
+    };
+    regions.unwrap_or_else(|e| {
+        eprintln!("Error reading {option} {arg}: {e}");
+        process::exit(1);
+    })
+}
+
+/// Reads the OSM extract at `path` given to `option`, exiting if it's invalid
+fn read_roads(path: &Path, option: &str) -> heatmap::osm::Roads {
+    heatmap::osm::load(path).unwrap_or_else(|e| {
+        eprintln!("Error reading {option} {}: {e}", path.display());
+        process::exit(1);
+    })
+}
+
+/// Short description of the period between `start` and `end` for legends, with the last day before `end` (which is exclusive) like --date-range
+fn period_label(start: Option<DateTime<Utc>>, end: Option<DateTime<Utc>>) -> String {
+    let end = end.map(|end| end - chrono::Duration::milliseconds(1));
+    match (start, end) {
+        (Some(start), Some(end)) => {
+            format!("{}..{}", start.format("%Y-%m-%d"), end.format("%Y-%m-%d"))
+        }
+        (Some(start), None) => format!("after {}", start.format("%Y-%m-%d")),
+        (None, Some(end)) => format!("through {}", end.format("%Y-%m-%d")),
+        (None, None) => "all".to_string(),
+    }
+}
+
+fn parse_positive(val: &str) -> Result<f64, String> {
+    match val.parse::<f64>() {
+        Ok(v) if v > 0.0 => Ok(v),
+        _ => Err(format!("must be a number greater than 0, got {val}")),
+    }
+}
+
+fn parse_non_negative(val: &str) -> Result<f64, String> {
+    match val.parse::<f64>() {
+        Ok(v) if v >= 0.0 => Ok(v),
+        _ => Err(format!("must be a number of at least 0, got {val}")),
+    }
+}
+
+fn parse_zoom(val: &str) -> Result<f64, String> {
+    match val.parse::<f64>() {
+        Ok(v) if (0.0..=22.0).contains(&v) => Ok(v),
+        _ => Err(format!("zoom must be a number from 0 to 22, got {val}")),
+    }
+}
+
+fn parse_year(val: &str) -> Result<heatmap::period::Period, String> {
+    if val.len() == 4 && val.chars().all(|c| c.is_ascii_digit()) {
+        val.parse()
+    } else {
+        Err(format!("year must be 4 digits (ex: 2024), got {val}"))
     }
 }
 